@@ -0,0 +1,259 @@
+use std::path::PathBuf;
+
+use clap::{ArgAction, Parser, ValueEnum};
+use dance_of_lines::map::Map;
+use dance_of_lines::render::BodyStyle;
+use dance_of_lines::snapshot::Snapshot;
+use dance_of_lines::terrain::Terrain;
+use dance_of_lines::theme::{load_theme_file, Theme};
+use dance_of_lines::Config;
+use rand::{thread_rng, Rng};
+
+/// `BodyStyle` as given on the command line, the library does not depend on clap
+#[derive(Clone, Copy, ValueEnum)]
+enum BodyStyleArg {
+    /// Every segment shows the direction it was left in
+    Lines,
+    /// Every segment joins the ones before and after it, turns become corners
+    Connected,
+}
+
+impl From<BodyStyleArg> for BodyStyle {
+    fn from(arg: BodyStyleArg) -> BodyStyle {
+        match arg {
+            BodyStyleArg::Lines => BodyStyle::Lines,
+            BodyStyleArg::Connected => BodyStyle::Connected,
+        }
+    }
+}
+
+/// `Theme` as given on the command line
+#[derive(Clone, Copy, ValueEnum)]
+enum ThemeArg {
+    /// Thin lines and symbols, not every font has all of them
+    Unicode,
+    /// Only characters every terminal can show
+    Ascii,
+    /// Shaded blocks
+    Blocks,
+}
+
+impl From<ThemeArg> for Theme {
+    fn from(arg: ThemeArg) -> Theme {
+        match arg {
+            ThemeArg::Unicode => Theme::Unicode,
+            ThemeArg::Ascii => Theme::Ascii,
+            ThemeArg::Blocks => Theme::Blocks,
+        }
+    }
+}
+
+/// `Terrain` as given on the command line
+#[derive(Clone, Copy, ValueEnum)]
+enum TerrainArg {
+    /// Scattered blobs of wall
+    Islands,
+    /// Rooms grown by a cellular automaton
+    Caves,
+    /// Corridors three cells wide
+    Maze,
+}
+
+impl From<TerrainArg> for Terrain {
+    fn from(arg: TerrainArg) -> Terrain {
+        match arg {
+            TerrainArg::Islands => Terrain::Islands,
+            TerrainArg::Caves => Terrain::Caves,
+            TerrainArg::Maze => Terrain::Maze,
+        }
+    }
+}
+
+/// A screensaver of lines dancing through your terminal
+#[derive(Parser)]
+#[command(name = "dance_of_lines", version)]
+pub struct Cli {
+    /// Show stats in the last row
+    #[arg(short = 'i', long, overrides_with = "no_stats", help_heading = "Display")]
+    stats: bool,
+    /// Hide the stats even if the config file shows them
+    #[arg(long, overrides_with = "stats", help_heading = "Display")]
+    no_stats: bool,
+    /// Invert leaning of snake body parts
+    #[arg(short = 'l', long, overrides_with = "no_invert_lean", help_heading = "Display")]
+    invert_lean: bool,
+    /// Keep the leaning of snake body parts even if the config file inverts it
+    #[arg(long, overrides_with = "invert_lean", help_heading = "Display")]
+    no_invert_lean: bool,
+    /// How snake bodies are drawn
+    #[arg(long, value_name = "STYLE", value_enum, help_heading = "Display")]
+    body_style: Option<BodyStyleArg>,
+    /// Glyphs to draw with
+    #[arg(long, value_name = "NAME", value_enum, help_heading = "Display")]
+    theme: Option<ThemeArg>,
+    /// Load the glyphs from a TOML file mapping element names to characters
+    #[arg(long, value_name = "PATH", conflicts_with = "theme", help_heading = "Display")]
+    theme_file: Option<PathBuf>,
+    /// Leave the last frame on screen instead of restoring the previous terminal content
+    #[arg(long, overrides_with = "alt_screen", help_heading = "Display")]
+    no_alt_screen: bool,
+    /// Draw on the alternate screen even if the config file turns it off
+    #[arg(long, overrides_with = "no_alt_screen", help_heading = "Display")]
+    alt_screen: bool,
+    /// Minimum delay between frames in milliseconds
+    #[arg(short = 'd', long, value_name = "MS", help_heading = "Display")]
+    delay: Option<u64>,
+    /// Size of the world, either as 80x24 or as two numbers; follows the terminal if not set
+    #[arg(short = 't', long, value_name = "WxH", num_args = 1..=2, action = ArgAction::Set, help_heading = "Display")]
+    size: Vec<String>,
+
+    /// Number of spawners
+    #[arg(short = 's', long, value_name = "COUNT", help_heading = "Simulation")]
+    spawners: Option<u16>,
+    /// Worker threads of the pathfinder, 0 disables threading
+    #[arg(short = 'p', long, value_name = "COUNT", help_heading = "Simulation")]
+    threads: Option<u32>,
+    /// Wrap the world around its edges instead of walling it in
+    #[arg(long, overrides_with = "no_wrap", help_heading = "Simulation")]
+    wrap: bool,
+    /// Wall the world in even if the config file wraps it
+    #[arg(long, overrides_with = "wrap", help_heading = "Simulation")]
+    no_wrap: bool,
+    /// Build the world from a text file: '#' wall, 'S' spawner, '*' food
+    #[arg(long, value_name = "PATH", help_heading = "Simulation")]
+    map: Option<PathBuf>,
+    /// Stretch the map over the whole world instead of centering it
+    #[arg(long, overrides_with = "no_scale_map", help_heading = "Simulation")]
+    scale_map: bool,
+    /// Center the map even if the config file stretches it
+    #[arg(long, overrides_with = "scale_map", help_heading = "Simulation")]
+    no_scale_map: bool,
+    /// Generate obstacles inside the world
+    #[arg(long, value_name = "KIND", value_enum, help_heading = "Simulation")]
+    terrain: Option<TerrainArg>,
+    /// Seed the simulation to reproduce a run
+    #[arg(long, help_heading = "Simulation")]
+    seed: Option<u64>,
+    /// Load settings from a TOML file [default: ~/.config/dance-of-lines/config.toml]
+    #[arg(long, value_name = "PATH", help_heading = "Simulation")]
+    config: Option<PathBuf>,
+    /// Load additional species from a TOML file, may be given several times [default: ~/.config/dance-of-lines/species.toml]
+    #[arg(long, value_name = "PATH", help_heading = "Simulation")]
+    species: Vec<PathBuf>,
+
+    /// Continue the run saved in a snapshot, its world keeps the size, seed and layout it was saved with
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["size", "seed", "wrap", "no_wrap", "map", "scale_map", "terrain", "spawners"], help_heading = "Simulation")]
+    load: Option<PathBuf>,
+
+    /// Record the changes of every iteration to a file
+    #[arg(long, value_name = "PATH", help_heading = "Recording")]
+    record: Option<PathBuf>,
+    /// Play back a recording instead of running the simulation
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["headless", "load", "record"], help_heading = "Recording")]
+    replay: Option<PathBuf>,
+
+    /// Run without a terminal and print a summary
+    #[arg(long, help_heading = "Headless")]
+    headless: bool,
+    /// Number of iterations to run in headless mode
+    #[arg(long, value_name = "COUNT", requires = "headless", help_heading = "Headless")]
+    ticks: Option<u64>,
+    /// Save a snapshot of the world after the last iteration
+    #[arg(long, value_name = "PATH", requires = "headless", help_heading = "Headless")]
+    save: Option<PathBuf>,
+}
+
+impl Cli {
+    /// Builds the validated configuration: defaults, overridden by the config file, overridden by the flags.
+    pub fn into_config(self) -> Result<Config, String> {
+        let mut config = Config::default();
+        // Species come first, so the config file can tune them like the built-in ones
+        let species_paths = match self.species.is_empty() {
+            true => default_config_path("species.toml").filter(|path| path.exists()).into_iter().collect(),
+            false => self.species,
+        };
+        for path in species_paths {
+            config.load_species_file(&path)?;
+        }
+        let config_path = self.config.or_else(|| default_config_path("config.toml").filter(|path| path.exists()));
+        if let Some(path) = config_path {
+            config.load_file(&path)?;
+        }
+
+        override_flag(&mut config.show_stats, self.stats, self.no_stats);
+        override_flag(&mut config.invert_lean, self.invert_lean, self.no_invert_lean);
+        if let Some(body_style) = self.body_style { config.body_style = body_style.into(); }
+        if let Some(theme) = self.theme { config.visuals = Theme::from(theme).visuals(); }
+        if let Some(path) = self.theme_file { config.visuals = load_theme_file(&path)?; }
+        override_flag(&mut config.alternate_screen, self.alt_screen, self.no_alt_screen);
+        if let Some(delay) = self.delay { config.min_delay = delay; }
+        if let Some(spawners) = self.spawners { config.spawners = spawners; }
+        if let Some(threads) = self.threads { config.max_threads = threads; }
+        if let Some(ticks) = self.ticks { config.ticks = ticks; }
+        override_flag(&mut config.wrap, self.wrap, self.no_wrap);
+        if let Some(map) = self.map { config.map = Some(Map::load(&map)?); }
+        override_flag(&mut config.scale_map, self.scale_map, self.no_scale_map);
+        if let Some(terrain) = self.terrain { config.terrain = Some(terrain.into()); }
+        config.headless = self.headless;
+        config.save = self.save;
+        config.record = self.record;
+        config.replay = self.replay;
+        config.seed = self.seed.unwrap_or_else(|| thread_rng().gen());
+
+        let mut size = parse_size(&self.size)?;
+        if let Some(path) = self.load {
+            let snapshot = Snapshot::load(&path)?;
+            // The world continues as it was, it can not follow the terminal
+            config.seed = snapshot.seed;
+            config.wrap = snapshot.canvas.wrap;
+            size = Some(snapshot.canvas.size);
+            config.snapshot = Some(snapshot);
+        }
+        config.fixed_size = size.is_some();
+        if config.headless {
+            // Without a terminal there is nothing to measure, keep the default size
+            if let Some(size) = size {
+                config.size = size;
+            }
+        } else {
+            let real_size = crossterm::terminal::size().map_err(|err| format!("Cannot get terminal size: {}", err))?;
+            config.size = match size {
+                Some(size) if size.0 > real_size.0 || size.1 > real_size.1 =>
+                    return Err(format!("A size of {}x{} does not fit into the terminal of {}x{}", size.0, size.1, real_size.0, real_size.1)),
+                Some(size) => size,
+                None => real_size,
+            };
+        }
+
+        if config.invert_lean {
+            config.swap_lean_visuals();
+        }
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// Sets `value` to whichever of a flag and its negation was given, the value of the config file stays otherwise.
+fn override_flag(value: &mut bool, on: bool, off: bool) {
+    if on || off {
+        *value = on;
+    }
+}
+
+/// Path of `file` in the config directory of dance of lines.
+fn default_config_path(file: &str) -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|config_dir| config_dir.join("dance-of-lines").join(file))
+}
+
+fn parse_size(values: &[String]) -> Result<Option<(u16, u16)>, String> {
+    let (columns, rows) = match values {
+        [] => return Ok(None),
+        [size] => size.split_once('x').ok_or_else(|| format!("Invalid size '{}', expected <columns>x<rows>", size))?,
+        [columns, rows] => (columns.as_str(), rows.as_str()),
+        _ => return Err(format!("Invalid size '{}', expected <columns>x<rows> or two numbers", values.join(" "))),
+    };
+    let parse = |value: &str| value.parse::<u16>().map_err(|_| format!("Invalid size '{}', expected a number between 0 and {}", value, u16::MAX));
+    Ok(Some((parse(columns)?, parse(rows)?)))
+}
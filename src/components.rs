@@ -1,23 +1,24 @@
 
 
 use crossterm::style::Color;
+use serde::{Deserialize, Serialize};
 
-use crate::TERM_SIZE;
+use crate::config::SpeciesSettings;
+use crate::genome::{GenePool, Genome};
+use crate::spatial::Grid;
+use crate::species::Species;
 
-#[derive(PartialEq, Clone, Hash, Eq, Copy)]
-pub enum Species {
-    NormalSnake,
-    DetachedSnake,
-    Wesp
-}
+/// Generator behind every random decision of a run, seeded once so a run can be reproduced.
+/// ChaCha is used over `StdRng` because its output is stable across rand releases.
+pub type SimRng = rand_chacha::ChaCha8Rng;
 
-#[derive(PartialEq, Clone, Copy)]
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub enum DirectionX {
     Left,
     Right,
     None,
 }
-#[derive(PartialEq, Clone, Copy)]
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub enum DirectionY {
     Up,
     Down,
@@ -25,7 +26,7 @@ pub enum DirectionY {
 }
 
 pub fn directions_to_check(current_dir: &(DirectionX, DirectionY), fov: isize) -> Vec<(DirectionX, DirectionY)> {
-    let circle = vec![
+    let circle = [
         (DirectionX::Left, DirectionY::None),
         (DirectionX::Left, DirectionY::Up),
         (DirectionX::None, DirectionY::Up),
@@ -54,7 +55,8 @@ pub fn directions_to_check(current_dir: &(DirectionX, DirectionY), fov: isize) -
     directions_to_check
 }
 
-pub fn pos_alteration_by_direction(dir_x: Option<&DirectionX>, dir_y: Option<&DirectionY>, position: &(u16, u16)) -> (i32, i32) {
+/// Position next to `position` in the given directions, wrapped around the edges of a world of size `wrap` if given.
+pub fn pos_alteration_by_direction(dir_x: Option<&DirectionX>, dir_y: Option<&DirectionY>, position: &(u16, u16), wrap: Option<(u16, u16)>) -> (i32, i32) {
     let mut new_position = (position.0 as i32, position.1 as i32);
     if let Some(dir_x) = dir_x {
         match dir_x {
@@ -70,12 +72,15 @@ pub fn pos_alteration_by_direction(dir_x: Option<&DirectionX>, dir_y: Option<&Di
             DirectionY::None => (),
         }
     }
+    if let Some(size) = wrap {
+        new_position = (new_position.0.rem_euclid(size.0 as i32), new_position.1.rem_euclid(size.1 as i32));
+    }
     new_position
 }
 
 
 
-#[derive(PartialEq, Eq, Hash, Copy, Clone)]
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug, Serialize, Deserialize)]
 
 pub enum Element {
     Wall,
@@ -88,30 +93,99 @@ pub enum Element {
     BodyPartHead,
     WespHead,
     WespBody,
+    /// Body segments of the connected body style, only drawn and never part of the world
+    LineVert,
+    LineHori,
+    LineLeftLean,
+    LineRightLean,
+    LineUpRight,
+    LineUpLeft,
+    LineDownRight,
+    LineDownLeft,
+    LineCross,
+    /// Parts of species defined in a species file, drawn with the glyphs given there
+    Head(Species),
+    Body(Species),
 }
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 
 pub struct Part {
-    pub(crate) element: Element,
-    pub(crate) position: (u16, u16),
-    pub(crate) color: Color,
+    pub element: Element,
+    pub position: (u16, u16),
+    pub color: Color,
 }
 
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 
-pub(crate) struct Creature {
-    pub(crate) parts: Vec<Part>,
-    pub(crate) color: Color,
-    pub(crate) species: Species,
-    pub(crate) curent_direction: (DirectionX, DirectionY),
-    pub(crate) spawner_at: (u16, u16),
-    pub(crate) killed: bool,
+pub struct Creature {
+    pub parts: Vec<Part>,
+    pub color: Color,
+    pub species: Species,
+    pub curent_direction: (DirectionX, DirectionY),
+    /// Spawner that released the creature, none for offspring
+    pub spawner_at: Option<(u16, u16)>,
+    pub killed: bool,
+    /// Moves left before the creature starts to starve
+    pub energy: u32,
+    /// Number of parts the creature grows or shrinks to, the tail is trimmed by `handle_killed`
+    pub length: usize,
+    /// Shared by a creature released by a spawner and all of its offspring
+    pub lineage: u32,
+    pub genome: Genome,
+    /// Iterations the creature has been alive for
+    pub age: u64,
 }
 
 impl Creature {
-    pub(crate) fn move_to(&mut self, new_position: (u16, u16), moved_in_direction: (DirectionX, DirectionY), colision: bool) {
-        if new_position.0 > TERM_SIZE.read().unwrap().0 || new_position.0 <= 0 ||  new_position.1 > TERM_SIZE.read().unwrap().1 || new_position.1 <= 0 || colision {
+    /// A newborn made of `head` only, with full energy and the growth of one meal ahead of it.
+    pub fn new(head: Part, curent_direction: (DirectionX, DirectionY), species: Species, spawner_at: Option<(u16, u16)>,
+               lineage: u32, genome: Genome, settings: &SpeciesSettings) -> Creature {
+        let mut creature = Creature { parts: vec![head], color: head.color, species, curent_direction, spawner_at,
+                                      killed: false, energy: settings.max_energy, length: 1, lineage, genome, age: 0 };
+        creature.grow(settings.meal_growth, settings);
+        creature
+    }
+
+    fn grow(&mut self, parts: usize, settings: &SpeciesSettings) {
+        self.length += parts;
+        if settings.max_length != 0 {
+            self.length = self.length.min(settings.max_length);
+        }
+    }
+
+    /// Pays for a move, a creature without energy left loses a part instead.
+    pub fn spend_energy(&mut self, settings: &SpeciesSettings) {
+        if settings.max_energy == 0 {
+            self.grow(1, settings);
+        } else if self.energy > 0 {
+            self.energy -= 1;
+        } else {
+            self.length = self.length.saturating_sub(1);
+        }
+    }
+
+    /// Restores energy and grows after eating food or prey.
+    pub fn feed(&mut self, settings: &SpeciesSettings) {
+        self.energy = (self.energy + settings.food_energy).min(settings.max_energy);
+        self.grow(settings.meal_growth, settings);
+    }
+
+    /// Whether the creature is long and fed enough to lay an offspring
+    pub fn can_breed(&self, settings: &SpeciesSettings) -> bool {
+        settings.breed_length != 0 && !self.killed && self.parts.len() >= settings.breed_length && self.energy * 2 >= settings.max_energy
+    }
+
+    /// Whether hunger has eaten the creature down to its head
+    pub fn starved(&self, settings: &SpeciesSettings) -> bool {
+        settings.max_energy != 0 && self.energy == 0 && self.length <= 1
+    }
+
+    /// Moves the head and leaves `part_to_append` behind on its old position, or kills the creature on a colision.
+    /// Leaving a world of `size` kills as well, a wrapped world without a size can not be left.
+    pub fn move_to(&mut self, new_position: (u16, u16), moved_in_direction: (DirectionX, DirectionY), part_to_append: Element, colision: bool, size: Option<(u16, u16)>) {
+        let outside = size.is_some_and(|size| new_position.0 > size.0 || new_position.0 == 0 ||  new_position.1 > size.1 || new_position.1 == 0);
+        if outside || colision {
             self.killed = true;
             return;
         }
@@ -119,66 +193,118 @@ impl Creature {
         self.parts[0].position = new_position;
         self.curent_direction = moved_in_direction;
         
-        let part_to_append = if self.species == Species::Wesp {
-            Element::WespBody
-        } else {
-            match moved_in_direction {
-                (DirectionX::None, DirectionY::Up) =>  Element::BodyPartVert,
-                (DirectionX::Right,DirectionY::Up) =>  Element::BodyPartLeftLean,
-                (DirectionX::Left, DirectionY::Up) =>  Element::BodyPartRightLean,
-                (DirectionX::None, DirectionY::Down) =>Element::BodyPartVert,
-                (DirectionX::Right,DirectionY::Down) =>Element::BodyPartRightLean,
-                (DirectionX::Left, DirectionY::Down) =>Element::BodyPartLeftLean,
-                (DirectionX::Right,DirectionY::None) =>Element::BodyPartHori,
-                (DirectionX::Left, DirectionY::None) =>Element::BodyPartHori,
-                _ => Element::BodyPartHori,
-            }
-        };
-        self.parts.append(&mut vec![Part { element: part_to_append,
-                                             position: old_position, color: self.color }]);
+        self.parts.push(Part { element: part_to_append,
+                                             position: old_position, color: self.color });
     
 }
 }
-#[derive(Clone)]
-pub(crate) struct CanvasParts {
-    pub(crate) alive: Vec<Creature>,
-    pub(crate) environment: Vec<Part>,
-    pub(crate) interactable: Vec<Part>, 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CanvasParts {
+    pub size: (u16, u16),
+    /// Leaving an edge enters the world again from the opposite one
+    pub wrap: bool,
+    pub alive: Vec<Creature>,
+    pub environment: Vec<Part>,
+    pub interactable: Vec<Part>, 
+    /// Lineages founded so far, the next creature released by a spawner founds lineage `lineages`
+    pub lineages: u32,
+    pub gene_pool: GenePool,
+    /// Occupancy of every position, only up to date while parts are changed through the methods below
+    #[serde(skip)]
+    pub(crate) grid: Grid,
 }
 
 impl CanvasParts{
-    pub(crate) fn add_element(&mut self, new_element: Element, position: (u16, u16), color: Option<Color>,
+    pub fn new(size: (u16, u16), wrap: bool) -> CanvasParts {
+        CanvasParts { size, wrap, alive: Vec::new(), environment: Vec::new(), interactable: Vec::new(), lineages: 0,
+                      gene_pool: GenePool::default(), grid: Grid::new(size, wrap) }
+    }
+
+    /// Size to wrap positions around, none if the world has edges
+    pub fn wrap_size(&self) -> Option<(u16, u16)> {
+        self.wrap.then_some(self.size)
+    }
+
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    /// Fills the grid from all parts again, needed after the parts were replaced as a whole.
+    pub fn rebuild_grid(&mut self) {
+        let mut grid = Grid::new(self.size, self.wrap);
+        for part in self.unify_elements() {
+            grid.insert(part.position, part.element);
+        }
+        self.grid = grid;
+    }
+
+    pub fn add_element(&mut self, new_element: Element, position: (u16, u16), color: Option<Color>,
     creature_index: Option<usize>){
-    if position.0 > TERM_SIZE.read().unwrap().0 || position.1 > TERM_SIZE.read().unwrap().1 {
+    if position.0 > self.size.0 || position.1 > self.size.1 {
         return;
     }
     
     match new_element {
         Element::Wall => self.environment.push(Part { element: new_element, position, color: color.unwrap() }),
         Element::Spawn | Element::Food => self.interactable.push(Part { element: new_element, position, color: color.unwrap() }),
-        Element::BodyPartVert | Element::BodyPartHori | Element::BodyPartLeftLean | Element::BodyPartRightLean | Element::WespBody => {
+        Element::BodyPartVert | Element::BodyPartHori | Element::BodyPartLeftLean | Element::BodyPartRightLean | Element::WespBody | Element::Body(_) => {
             if let Some(index) = creature_index {
                 let color = self.alive[index].color;
-                self.alive[index].parts.push(Part { element: new_element, position, color: color });
+                self.alive[index].parts.push(Part { element: new_element, position, color });
             } else {
                 panic!("No creature index provided");
             }
         }
         _ => panic!("Creature heads may not be added with this function. Use add_creature instead."),
     }
+    self.grid.insert(position, new_element);
 }
-    pub(crate) fn add_creature(&mut self, position: (u16, u16), color: Color, curent_direction: (DirectionX, DirectionY), species: Species, spawner_at: (u16, u16)) {
-        let head = match species {
-            Species::Wesp => Element::WespHead,
-            _ => Element::BodyPartHead,
-        };
-            
-        self.alive.push(Creature {parts: vec![Part { element: head, position: position, color: color }],
-                                 color: color, curent_direction: curent_direction, killed: false,
-                                 species: species, spawner_at: spawner_at});
-    }
-
-    pub(crate) fn unify_elements(&self) -> Vec<&Part> {
+    /// Number of lineages with at least one living creature
+    pub fn living_lineages(&self) -> usize {
+        let mut lineages: Vec<u32> = self.alive.iter().map(|creature| creature.lineage).collect();
+        lineages.sort_unstable();
+        lineages.dedup();
+        lineages.len()
+    }
+
+    pub fn add_creature(&mut self, creature: Creature) {
+        for part in creature.parts.iter() {
+            self.grid.insert(part.position, part.element);
+        }
+        self.alive.push(creature);
+    }
+
+    /// Moves the creature at `index` like `Creature::move_to` and keeps the grid in sync.
+    pub fn move_creature(&mut self, index: usize, new_position: (u16, u16), moved_in_direction: (DirectionX, DirectionY), part_to_append: Element, colision: bool) {
+        let old_head = self.alive[index].parts[0];
+        let old_length = self.alive[index].parts.len();
+        self.alive[index].move_to(new_position, moved_in_direction, part_to_append, colision, (!self.wrap).then_some(self.size));
+        // A creature that got killed instead of moving keeps its parts where they are
+        if self.alive[index].parts.len() > old_length {
+            let appended = self.alive[index].parts[old_length];
+            self.grid.remove(old_head.position, old_head.element);
+            self.grid.insert(new_position, old_head.element);
+            self.grid.insert(appended.position, appended.element);
+        }
+    }
+
+    pub fn remove_creature_part(&mut self, creature_index: usize, part_index: usize) -> Part {
+        let part = self.alive[creature_index].parts.remove(part_index);
+        self.grid.remove(part.position, part.element);
+        part
+    }
+
+    /// Removes the food at `position` and returns whether there was any.
+    pub fn remove_food(&mut self, position: (u16, u16)) -> bool {
+        if !self.grid.at(position).contains(&Element::Food) {
+            return false;
+        }
+        self.interactable.retain(|part| part.position != position || part.element != Element::Food);
+        self.grid.remove(position, Element::Food);
+        true
+    }
+
+    pub fn unify_elements(&self) -> Vec<&Part> {
         let unified_elements = Vec::from_iter(self.environment.iter()
                         .chain(self.interactable.iter())
                         .chain(self.alive.iter().flat_map(|creature| creature.parts.iter())));
@@ -187,7 +313,23 @@ impl CanvasParts{
 }
 
 pub struct CanvasState {
-    pub(crate) iterations: u128,
-    pub(crate) cleared_coords: Vec<(u16, u16)>,
-    //pub(crate) food_rate: u8,
+    pub iterations: u128,
+    /// Creatures that died in the last iteration
+    pub deaths: Vec<Death>,
+    //pub food_rate: u8,
+}
+
+impl CanvasState {
+    pub fn new(iterations: u128) -> CanvasState {
+        CanvasState { iterations, deaths: Vec::new() }
+    }
+}
+
+/// Where and as what a creature died, reported once its head is removed.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Death {
+    pub position: (u16, u16),
+    pub species: Species,
+    pub lineage: u32,
+    pub age: u64,
 }
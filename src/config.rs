@@ -0,0 +1,314 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::components::Element;
+use crate::genome::MAX_SIGHT_RADIUS;
+use crate::map::Map;
+use crate::snapshot::Snapshot;
+use crate::render::BodyStyle;
+use crate::terrain::Terrain;
+use crate::theme::{check_glyph_width, load_theme_file, Theme};
+use crate::species::{builtin_species, element_by_name, SpeciesDefinition, SpeciesRegistry};
+
+/// Behavior of a single species.
+#[derive(Clone, Copy)]
+pub struct SpeciesSettings {
+    /// Number of directions to each side of the current one a creature may turn to
+    pub fov: isize,
+    /// Moves per iteration
+    pub speed: u8,
+    pub sight_radius: i32,
+    /// Parts beyond this length are trimmed from the tail, 0 lets the creature grow forever
+    pub max_length: usize,
+    /// Relative chance of this species being picked when a spawner releases a creature
+    pub spawn_weight: u32,
+    /// Energy at spawn and the most a creature can store, every move costs one.
+    /// 0 disables hunger, the creature then grows with every move
+    pub max_energy: u32,
+    /// Energy restored by eating food or killing prey
+    pub food_energy: u32,
+    /// Parts grown per meal, a newborn grows as much as after its first meal
+    pub meal_growth: usize,
+    /// Length from which a creature with at least half of its energy lays an offspring, 0 never breeds
+    pub breed_length: usize,
+}
+
+/// Every setting of a run, built once by the frontend and handed to the simulation.
+#[derive(Clone)]
+pub struct Config {
+    /// Worker threads searching the paths of the creatures, 0 searches on the simulation thread
+    pub max_threads: u32,
+    pub spawners: u16,
+    pub show_stats: bool,
+    /// Draw on the alternate screen, so the terminal content is restored on exit
+    pub alternate_screen: bool,
+    /// Minimum delay between two frames in milliseconds
+    pub min_delay: u64,
+    /// Size of the world in columns and rows
+    pub size: (u16, u16),
+    /// The size was set by the user and does not follow the terminal
+    pub fixed_size: bool,
+    pub seed: u64,
+    pub headless: bool,
+    /// Iterations to run in headless mode
+    pub ticks: u64,
+    /// Snapshot written at the end of a headless run
+    pub save: Option<PathBuf>,
+    /// File the changes of every iteration are recorded to
+    pub record: Option<PathBuf>,
+    /// Recording to play back instead of running the simulation
+    pub replay: Option<PathBuf>,
+    pub visuals: HashMap<Element, char>,
+    /// Swap the glyphs of the leaning body parts, applied by the frontend through `swap_lean_visuals`
+    pub invert_lean: bool,
+    /// How the bodies of snakes are drawn
+    pub body_style: BodyStyle,
+    /// Chance of a food item appearing per iteration
+    pub food_probability: f64,
+    /// Chance of each unused spawner releasing a creature per iteration
+    pub spawn_chance: f64,
+    /// Leave the world on one edge to enter it again on the opposite one, instead of walls around it
+    pub wrap: bool,
+    /// Level to build the world from, inside the border walls
+    pub map: Option<Map>,
+    /// Stretch the map over the whole world instead of centering it
+    pub scale_map: bool,
+    /// Obstacles generated inside the world
+    pub terrain: Option<Terrain>,
+    /// Chance of each gene changing when it is passed on
+    pub mutation_rate: f64,
+    /// Settings of every species by name
+    pub species: BTreeMap<String, SpeciesSettings>,
+    /// Species loaded from species files, registered after the built-in ones
+    pub species_definitions: Vec<SpeciesDefinition>,
+    /// World to continue instead of building a new one, taken by the simulation when it is created
+    pub snapshot: Option<Snapshot>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            max_threads: 0,
+            spawners: 4,
+            show_stats: false,
+            alternate_screen: true,
+            min_delay: 17,
+            size: (80, 24),
+            fixed_size: false,
+            seed: 0,
+            headless: false,
+            ticks: 1000,
+            save: None,
+            record: None,
+            replay: None,
+            visuals: default_visuals(),
+            invert_lean: false,
+            body_style: BodyStyle::Lines,
+            food_probability: 0.30,
+            spawn_chance: 0.01,
+            wrap: false,
+            map: None,
+            scale_map: false,
+            terrain: None,
+            mutation_rate: 0.1,
+            species: builtin_species().iter().map(|behavior| (behavior.name().to_string(), behavior.default_settings())).collect(),
+            species_definitions: Vec::new(),
+            snapshot: None,
+        }
+    }
+}
+
+impl Config {
+    /// Checks that a world can be built from this configuration.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.spawners == 0 {
+            return Err("At least one spawner is needed".to_string());
+        }
+        // Every spawner gets its own vertical band, which needs room for at least one free column
+        if (self.size.0.saturating_sub(1)) / self.spawners < 2 {
+            return Err(format!("A width of {} is too narrow for {} spawners", self.size.0, self.spawners));
+        }
+        if self.size.1 < 3 {
+            return Err(format!("A height of {} leaves no room inside the walls", self.size.1));
+        }
+        if !(0.0..=1.0).contains(&self.food_probability) {
+            return Err(format!("The food probability {} is not between 0 and 1", self.food_probability));
+        }
+        if !(0.0..=1.0).contains(&self.spawn_chance) {
+            return Err(format!("The spawn chance {} is not between 0 and 1", self.spawn_chance));
+        }
+        if !(0.0..=1.0).contains(&self.mutation_rate) {
+            return Err(format!("The mutation rate {} is not between 0 and 1", self.mutation_rate));
+        }
+        for (name, species) in self.species.iter() {
+            // Turning further than half the circle would check directions twice
+            if !(0..=4).contains(&species.fov) {
+                return Err(format!("The fov of {} has to be between 0 and 4", name));
+            }
+            if !(1..=MAX_SIGHT_RADIUS).contains(&species.sight_radius) {
+                return Err(format!("The sight radius of {} has to be between 1 and {}", name, MAX_SIGHT_RADIUS));
+            }
+        }
+        if self.species.values().all(|species| species.spawn_weight == 0) {
+            return Err("At least one species needs a spawn weight above 0".to_string());
+        }
+        // Resolves the element and species names used by species files
+        let registry = SpeciesRegistry::from_config(self)?;
+        if let Some(snapshot) = &self.snapshot {
+            let names: Vec<String> = registry.iter().map(|(_, behavior, _)| behavior.name().to_string()).collect();
+            if snapshot.species != names {
+                return Err(format!("The snapshot was saved with the species {}, but {} are known", snapshot.species.join(", "), names.join(", ")));
+            }
+        }
+        let glyphs = self.visuals.iter().map(|(element, glyph)| (*element, *glyph))
+            .chain(registry.iter().flat_map(|(_, behavior, _)| behavior.glyphs()));
+        for (element, glyph) in glyphs {
+            check_glyph_width(&registry.element_name(element), glyph)?;
+        }
+        Ok(())
+    }
+
+    /// Adds the species defined in the TOML file at `path`, each under a name not taken yet.
+    pub fn load_species_file(&mut self, path: &Path) -> Result<(), String> {
+        let content = fs::read_to_string(path).map_err(|err| format!("Cannot read {}: {}", path.display(), err))?;
+        let file: BTreeMap<String, SpeciesDefinition> = toml::from_str(&content).map_err(|err| format!("Invalid species file {}: {}", path.display(), err))?;
+
+        for (name, mut definition) in file {
+            if self.species.contains_key(&name) {
+                return Err(format!("The species {} in {} is already defined", name, path.display()));
+            }
+            // The parts of the species would be named like an existing element
+            if element_by_name(&format!("{}_head", name), &[]).is_some() || element_by_name(&format!("{}_body", name), &[]).is_some() {
+                return Err(format!("The species name {} in {} is reserved", name, path.display()));
+            }
+            definition.name = name.clone();
+            self.species.insert(name, definition.settings());
+            self.species_definitions.push(definition);
+        }
+        Ok(())
+    }
+
+    /// Overrides every setting present in the TOML file at `path`.
+    pub fn load_file(&mut self, path: &Path) -> Result<(), String> {
+        let content = fs::read_to_string(path).map_err(|err| format!("Cannot read {}: {}", path.display(), err))?;
+        let file: ConfigFile = toml::from_str(&content).map_err(|err| format!("Invalid config file {}: {}", path.display(), err))?;
+
+        if let Some(max_threads) = file.max_threads { self.max_threads = max_threads; }
+        if let Some(spawners) = file.spawners { self.spawners = spawners; }
+        if let Some(show_stats) = file.show_stats { self.show_stats = show_stats; }
+        if let Some(alternate_screen) = file.alternate_screen { self.alternate_screen = alternate_screen; }
+        if let Some(min_delay) = file.min_delay { self.min_delay = min_delay; }
+        if let Some(invert_lean) = file.invert_lean { self.invert_lean = invert_lean; }
+        if let Some(body_style) = file.body_style { self.body_style = body_style; }
+        if let Some(food_probability) = file.food_probability { self.food_probability = food_probability; }
+        if let Some(spawn_chance) = file.spawn_chance { self.spawn_chance = spawn_chance; }
+        if let Some(wrap) = file.wrap { self.wrap = wrap; }
+        if let Some(map) = file.map {
+            // Relative to the config file rather than to wherever the program was started
+            let map_path = path.parent().map_or(map.clone(), |directory| directory.join(&map));
+            self.map = Some(Map::load(&map_path)?);
+        }
+        if let Some(scale_map) = file.scale_map { self.scale_map = scale_map; }
+        if let Some(terrain) = file.terrain { self.terrain = Some(terrain); }
+        if let Some(mutation_rate) = file.mutation_rate { self.mutation_rate = mutation_rate; }
+        if let Some(theme) = file.theme { self.visuals = theme.visuals(); }
+        if let Some(theme_file) = file.theme_file {
+            let theme_path = path.parent().map_or(theme_file.clone(), |directory| directory.join(&theme_file));
+            self.visuals = load_theme_file(&theme_path)?;
+        }
+        for (name, species_file) in file.species {
+            let settings = self.species.get_mut(&name).ok_or_else(|| format!("Unknown species '{}' in {}", name, path.display()))?;
+            species_file.apply(settings);
+        }
+        Ok(())
+    }
+
+    /// Swaps the glyphs of the leaning body parts.
+    /// The connected body style joins its diagonals by where the neighbours are, so its glyphs stay.
+    pub fn swap_lean_visuals(&mut self) {
+        let right = self.visuals[&Element::BodyPartRightLean];
+        let left = self.visuals[&Element::BodyPartLeftLean];
+        self.visuals.insert(Element::BodyPartRightLean, left);
+        self.visuals.insert(Element::BodyPartLeftLean, right);
+    }
+}
+
+pub fn default_visuals() -> HashMap<Element, char> {
+    HashMap::from([
+        (Element::Wall, '𐲕'),
+        (Element::Spawn, '⬟'),
+        (Element::BodyPartVert, '|'),
+        (Element::BodyPartHori, '-'),
+        (Element::BodyPartRightLean, '\\'),
+        (Element::BodyPartLeftLean, '/'),
+        (Element::Food, '#'),
+        (Element::BodyPartHead, '█'),
+        (Element::WespHead, '0'),
+        (Element::WespBody, '•'),
+        (Element::LineVert, '│'),
+        (Element::LineHori, '─'),
+        (Element::LineRightLean, '╲'),
+        (Element::LineLeftLean, '╱'),
+        (Element::LineUpRight, '╰'),
+        (Element::LineUpLeft, '╯'),
+        (Element::LineDownRight, '╭'),
+        (Element::LineDownLeft, '╮'),
+        (Element::LineCross, '╳'),
+    ])
+}
+
+/// Layout of the config file, every key is optional and only overrides what is present.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    max_threads: Option<u32>,
+    spawners: Option<u16>,
+    show_stats: Option<bool>,
+    alternate_screen: Option<bool>,
+    min_delay: Option<u64>,
+    invert_lean: Option<bool>,
+    body_style: Option<BodyStyle>,
+    food_probability: Option<f64>,
+    spawn_chance: Option<f64>,
+    wrap: Option<bool>,
+    map: Option<PathBuf>,
+    scale_map: Option<bool>,
+    terrain: Option<Terrain>,
+    mutation_rate: Option<f64>,
+    theme: Option<Theme>,
+    theme_file: Option<PathBuf>,
+    /// Keyed by the name of the species
+    #[serde(default)]
+    species: HashMap<String, SpeciesFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SpeciesFile {
+    fov: Option<isize>,
+    speed: Option<u8>,
+    sight_radius: Option<i32>,
+    max_length: Option<usize>,
+    spawn_weight: Option<u32>,
+    max_energy: Option<u32>,
+    food_energy: Option<u32>,
+    meal_growth: Option<usize>,
+    breed_length: Option<usize>,
+}
+
+impl SpeciesFile {
+    fn apply(&self, settings: &mut SpeciesSettings) {
+        if let Some(fov) = self.fov { settings.fov = fov; }
+        if let Some(speed) = self.speed { settings.speed = speed; }
+        if let Some(sight_radius) = self.sight_radius { settings.sight_radius = sight_radius; }
+        if let Some(max_length) = self.max_length { settings.max_length = max_length; }
+        if let Some(spawn_weight) = self.spawn_weight { settings.spawn_weight = spawn_weight; }
+        if let Some(max_energy) = self.max_energy { settings.max_energy = max_energy; }
+        if let Some(food_energy) = self.food_energy { settings.food_energy = food_energy; }
+        if let Some(meal_growth) = self.meal_growth { settings.meal_growth = meal_growth; }
+        if let Some(breed_length) = self.breed_length { settings.breed_length = breed_length; }
+    }
+}
@@ -0,0 +1,91 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::components::{Element, SimRng};
+use crate::config::SpeciesSettings;
+use crate::species::{Species, SpeciesBehavior};
+
+/// The search grows with every step of sight, so neither settings nor evolution may push it too far
+pub const MAX_SIGHT_RADIUS: i32 = 12;
+
+/// Genomes of the oldest creatures kept per species
+const ELDERS_PER_SPECIES: usize = 16;
+
+/// What a creature inherits: how it weighs the elements it sees and how far and wide it looks.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Genome {
+    /// Attraction of every element, the pathfinder follows the highest sum
+    pub weights: Vec<(Element, i64)>,
+    pub fov: isize,
+    pub sight_radius: i32,
+    /// Number of mutations between this genome and the one of its species
+    pub generation: u32,
+}
+
+impl Genome {
+    /// The genome every creature of a species starts with, taken from its behavior and settings.
+    pub fn new(behavior: &dyn SpeciesBehavior, settings: &SpeciesSettings, elements: &[Element]) -> Genome {
+        Genome {
+            weights: elements.iter().map(|element| (*element, behavior.path_value(*element))).collect(),
+            fov: settings.fov,
+            sight_radius: settings.sight_radius,
+            generation: 0,
+        }
+    }
+
+    pub fn weight(&self, element: Element) -> i64 {
+        self.weights.iter().find(|(weighted, _)| *weighted == element).map_or(0, |(_, weight)| *weight)
+    }
+
+    /// A copy where every gene changes with a chance of `rate`.
+    pub fn mutate(&self, rate: f64, rand_gen: &mut SimRng) -> Genome {
+        let mut genome = self.clone();
+        for (_, weight) in genome.weights.iter_mut() {
+            if rand_gen.gen_bool(rate) {
+                *weight += rand_gen.gen_range(-10..=10);
+            }
+        }
+        if rand_gen.gen_bool(rate) {
+            genome.fov = (genome.fov + rand_gen.gen_range(-1..=1)).clamp(0, 4);
+        }
+        if rand_gen.gen_bool(rate) {
+            genome.sight_radius = (genome.sight_radius + rand_gen.gen_range(-1..=1)).clamp(1, MAX_SIGHT_RADIUS);
+        }
+        genome.generation += 1;
+        genome
+    }
+}
+
+/// Genomes of the longest surviving creatures, respawns are drawn from here.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct GenePool {
+    /// Species, iterations survived and genome, the oldest first
+    pub elders: Vec<(Species, u64, Genome)>,
+}
+
+impl GenePool {
+    /// Keeps the genome of a dead creature if it survived longer than the elders of its species.
+    pub fn record(&mut self, species: Species, age: u64, genome: &Genome) {
+        let position = self.elders.iter().position(|(_, elder_age, _)| *elder_age < age).unwrap_or(self.elders.len());
+        self.elders.insert(position, (species, age, genome.clone()));
+        // Only the youngest of the species can have fallen out
+        if self.elders.iter().filter(|(elder, _, _)| *elder == species).count() > ELDERS_PER_SPECIES {
+            let youngest = self.elders.iter().rposition(|(elder, _, _)| *elder == species).unwrap();
+            self.elders.remove(youngest);
+        }
+    }
+
+    /// A random genome among the elders of `species`, none before one of them died.
+    pub fn pick(&self, species: Species, rand_gen: &mut SimRng) -> Option<&Genome> {
+        let elders: Vec<&Genome> = self.elders.iter().filter(|(elder, _, _)| *elder == species).map(|(_, _, genome)| genome).collect();
+        if elders.is_empty() {
+            return None;
+        }
+        Some(elders[rand_gen.gen_range(0..elders.len())])
+    }
+
+    /// The genome of the longest surviving creature of `species`, with the iterations it survived
+    pub fn oldest(&self, species: Species) -> Option<(u64, &Genome)> {
+        self.elders.iter().find(|(elder, _, _)| *elder == species).map(|(_, age, genome)| (*age, genome))
+    }
+}
@@ -0,0 +1,20 @@
+//! The dance of lines simulation engine, free of any terminal handling.
+//! Frontends create a [`Simulation`], call [`Simulation::step`] once per frame and draw its canvas,
+//! for example with [`render::draw_canvas`] on a [`render::Renderer`].
+pub mod components;
+pub mod config;
+pub mod genome;
+pub mod map;
+pub mod part_handler;
+pub mod pathfinder;
+pub mod recording;
+pub mod render;
+mod simulation;
+pub mod snapshot;
+pub mod spatial;
+pub mod species;
+pub mod terrain;
+pub mod theme;
+
+pub use config::Config;
+pub use simulation::Simulation;
@@ -1,219 +1,194 @@
 #[doc(inline)]
 pub use std; // for documentation purposes
-use std::{char, f32::consts::E, io::{self, Chain, Write}};
-use components::CanvasParts;
-use crossterm::{cursor::MoveTo, event::{poll, read, Event, KeyCode, KeyEvent, KeyModifiers}, execute, queue, style::{Color, PrintStyledContent, Stylize}, terminal::{disable_raw_mode, enable_raw_mode, DisableLineWrap, EnableLineWrap}, QueueableCommand };
-use lazy_static::lazy_static; 
-//lazy_static is ok, mutability not needed
-use rand::{thread_rng, Rng};
+use std::io;
+use dance_of_lines::components::{self, CanvasParts};
+use dance_of_lines::recording::Recorder;
+use dance_of_lines::render::{self, Renderer};
+use dance_of_lines::{Config, Simulation};
+use crossterm::{event::{poll, read, Event, KeyCode, KeyEvent, KeyModifiers}, style::Color};
+use std::error::Error;
+use std::path::Path;
+use std::sync::atomic::Ordering;
 use std::time::Duration;
 use std::thread::sleep;
 use std::time::Instant;
-use std::collections::HashMap;
-use std::sync::RwLock; // RwLock is ok, mutability not needed 
-use std::env::args;
 use std::process::exit;
+use clap::Parser;
 
-mod part_handler;
-mod components;
-mod pathfinder;
+mod cli;
+mod player;
+mod terminal;
 
+use terminal::{CrosstermRenderer, TerminalGuard};
 
-lazy_static!{static ref MAX_THREADS: RwLock<u32> = RwLock::new(0);}
-
-lazy_static!{static ref SPAWNERS: RwLock<u16> = RwLock::new(0);}
-
-lazy_static!{static ref RESTART: RwLock<bool> = RwLock::new(false);}
-
-lazy_static!{static ref  SHOW_STATS:  RwLock<bool> = RwLock::new(false);}
-
-lazy_static!{static ref MIN_DELAY: RwLock<u64> = RwLock::new(17);}
-
-lazy_static! {
-    static ref TERM_SIZE: RwLock<(u16, u16)> = RwLock::new((0, 0));
+/// What the user asked for with the latest key presses
+enum Input {
+    Nothing,
+    Restart,
+    /// Write a snapshot of the world to the working directory
+    Save,
+    Quit,
 }
 
-lazy_static!{
-    static ref ELEMENT_VISUALS: RwLock<HashMap<components::Element, char>> = RwLock::new(HashMap::from([
-        (components::Element::Wall, '𐲕'),
-        (components::Element::Spawn, '⬟'),
-        (components::Element::BodyPartVert, '|'),
-        (components::Element::BodyPartHori, '-'),
-        (components::Element::BodyPartRightLean, '\\'),
-        (components::Element::BodyPartLeftLean, '/'),
-        (components::Element::Food, '#'),
-        (components::Element::BodyPartHead, '█'),
-        (components::Element::WespHead, '0'),
-        (components::Element::WespBody, '•'),
-    ]));
+fn handle_kb_input(config: &mut Config) -> io::Result<Input> {
+    if poll(Duration::from_millis(0))? {
+        match read()? {
+            Event::Key(KeyEvent{code: KeyCode::Char('c'), modifiers: KeyModifiers::CONTROL, kind: _, state: _}) => return Ok(Input::Quit),
+            Event::Key(KeyEvent{code: KeyCode::Char('s'), modifiers: _, kind: _, state: _}) => config.show_stats = !config.show_stats,
+            Event::Key(KeyEvent{code: KeyCode::Char('r'), modifiers: _, kind: _, state: _}) => return Ok(Input::Restart),
+            Event::Key(KeyEvent{code: KeyCode::Char('w'), modifiers: _, kind: _, state: _}) => return Ok(Input::Save),
+            Event::Resize(_,_) => return Ok(Input::Restart),
+            _ => (),
+        }
     }
-fn help_message() {
-    println!("Usage: dance_of_lines [options]
-    -i: Show stats
-    -s <int>: Set spawner count
-    -l: Invert leaning of snake body parts
-    -d <int>: Set minimum delay between frames in milliseconds
-    -t <int> <int>: Set terminal size in columns and rows
-    -p <int>: Set maximum thread count
-    -h, --help: Show this message
-    ");
-    exit(0);
+    Ok(Input::Nothing)
 }
 
-fn set_runtime_constants(cl_args: std::env::Args) {
-    let mut args = cl_args.skip(1);
-    let mut stats = false;
-    let mut min_delay = 17;
-    let mut max_term_size = (0, 0);
-    let mut spawners = 4;
-    let mut max_threads = 0;
-    let mut invert_lean = false;
-    while let Some(arg) = args.next() {
-        match arg.as_str() {
-            "-i" => stats = true,
-            "-l" => invert_lean = true,
-            "-s" => spawners = args.next().unwrap().parse().unwrap_or_else(|_| panic!("Invalid spawner count")),
-            "-d" => min_delay = args.next().unwrap().parse().unwrap_or_else(|_| panic!("Invalid delay")),
-            "-t" => max_term_size = (args.next().unwrap().parse().unwrap_or_else(|_| panic!("Invalid terminal size")), args.next().unwrap().parse().unwrap_or_else(|_| panic!("Invalid terminal size"))),
-            "-p" => max_threads = args.next().unwrap().parse().unwrap_or_else(|_| panic!("Invalid thread count")),
-            "-h" | "--help" => help_message(),
-            _ => panic!("Invalid argument --help for help"),
-        }
-    }
-    if max_term_size.0 > TERM_SIZE.read().unwrap().0 || max_term_size.1 > TERM_SIZE.read().unwrap().1 {
-        panic!("Terminal size too large");
-    } else if max_term_size == (0, 0) {
-        max_term_size = crossterm::terminal::size().unwrap_or_else(|_| panic!("Cannot get terminal size"));
-    }
-    if invert_lean {
-        ELEMENT_VISUALS.write().unwrap().insert(components::Element::BodyPartRightLean, '/');
-        ELEMENT_VISUALS.write().unwrap().insert(components::Element::BodyPartLeftLean, '\\');
+
+/// Draws the next frame, the stats bar covers the last row.
+fn draw_canvas(renderer: &mut impl Renderer, canvas: &CanvasParts, config: &Config, stats: &str) -> io::Result<()> {
+    renderer.clear();
+    render::draw_canvas(renderer, canvas, config);
+    if config.show_stats {
+        renderer.draw_text((0, canvas.size.1 - 1), stats, Color::Rgb { r: 255, g: 60, b: 70 });
     }
-    *MAX_THREADS.write().unwrap() = max_threads;
-    *SPAWNERS.write().unwrap() = spawners;
-    *SHOW_STATS.write().unwrap() = stats;
-    *MIN_DELAY.write().unwrap() = min_delay;
-    *TERM_SIZE.write().unwrap() = max_term_size;
+    renderer.present()
 }
 
-fn exit_handler() {
-    let _ = disable_raw_mode();
-    let mut stdout = io::stdout();
-    stdout.queue(crossterm::cursor::Show).unwrap();
-    stdout.queue(DisableLineWrap).unwrap();
-    stdout.flush().unwrap();
-    exit(0);
+/// A recorder for the run if one was asked for
+fn create_recorder(simulation: &Simulation) -> Result<Option<Recorder>, String> {
+    simulation.config.record.as_ref().map(|path| Recorder::create(path, simulation)).transpose()
 }
 
+fn run_headless(config: Config) -> Result<(), String> {
+    let mut simulation = Simulation::new(config);
+    let mut recorder = create_recorder(&simulation)?;
+    let started = Instant::now();
 
-fn handle_kb_input() {
-    if poll(Duration::from_millis(0)).unwrap() {
-        match read().unwrap() {
-            Event::Key(KeyEvent{code: KeyCode::Char('c'), modifiers: KeyModifiers::CONTROL, kind: _, state: _}) => exit_handler(),
-            Event::Key(KeyEvent{code: KeyCode::Char('s'), modifiers: _, kind: _, state: _}) => {let curent_state = *SHOW_STATS.read().unwrap(); *SHOW_STATS.write().unwrap() = !curent_state;},
-            Event::Key(KeyEvent{code: KeyCode::Char('r'), modifiers: _, kind: _, state: _}) => {*RESTART.write().unwrap() = true;},
-            Event::Resize(_,_) => {*RESTART.write().unwrap() = true;},
-            _ => (),
+    for _ in 0..simulation.config.ticks {
+        simulation.step();
+        if let Some(recorder) = recorder.as_mut() {
+            recorder.record(&simulation)?;
         }
     }
-}
-
 
-fn create_canvas() -> CanvasParts{
-    let mut rng = thread_rng();
-    let mut canvas = CanvasParts {alive: Vec::new(), environment: Vec::new(), interactable: Vec::new()}; // Create an empty vector to store the parts
-    let wall_color = Color::Rgb { r: 255, g: 60, b: 70 }; // Create a new color for the walls    
-
-    for y in 0..TERM_SIZE.read().unwrap().1 {
-        if y == 0 || y == TERM_SIZE.read().unwrap().1 - 1 {
-            for x in 0..TERM_SIZE.read().unwrap().0 {
-                canvas.add_element(components::Element::Wall, (x, y), Some(wall_color), None);
-            }
-        } else {
-            canvas.add_element(components::Element::Wall, (TERM_SIZE.read().unwrap().0 - 1, y), Some(wall_color), None); // Use array indexing instead of tuple indexing
-            canvas.add_element(components::Element::Wall, (0, y), Some(wall_color), None); // Use array indexing instead of tuple indexing
+    let elapsed = started.elapsed();
+    let canvas = &simulation.canvas;
+    let species_counts: Vec<String> = simulation.species.iter()
+        .map(|(species, behavior, _)| format!("{}: {}", behavior.name(), canvas.alive.iter().filter(|creature| creature.species == species).count()))
+        .collect();
+    println!("Seed: {}", simulation.config.seed);
+    println!("Size: {}x{}", canvas.size.0, canvas.size.1);
+    println!("Iterations: {}", simulation.state.iterations);
+    println!("Elapsed: {:.2?} ({:.2} iterations/s)", elapsed, simulation.state.iterations as f64 / elapsed.as_secs_f64());
+    println!("Creatures: {} ({})", canvas.alive.len(), species_counts.join(", "));
+    println!("Lineages: {} living of {}", canvas.living_lineages(), canvas.lineages);
+    for (species, behavior, _) in simulation.species.iter() {
+        if let Some((age, genome)) = canvas.gene_pool.oldest(species) {
+            let weights: Vec<String> = genome.weights.iter().map(|(element, weight)| format!("{} {}", simulation.species.element_name(*element), weight)).collect();
+            println!("Oldest {}: survived {} iterations, generation {}, fov {}, sight {}, weights {}",
+                behavior.name(), age, genome.generation, genome.fov, genome.sight_radius, weights.join(", "));
         }
     }
-    let spawner_ranges = (TERM_SIZE.read().unwrap().0-1) / *SPAWNERS.read().unwrap() as u16;
-    for i in 0..*SPAWNERS.read().unwrap(){
-    // Use array indexing instead of tuple indexing
-        canvas.add_element(components::Element::Spawn, (rng.gen_range(((spawner_ranges*i)+1)..spawner_ranges*(i+1)), rng.gen_range(1..TERM_SIZE.read().unwrap().1-1)), Some(Color::Rgb { r: 10, g: 255, b: 10 }), None);
-    }
-    
-    canvas // Return the canvas vector
-}
-
-fn draw_canvas(canvas: &CanvasParts, cleared_coords: &mut Vec<(u16, u16)>) {
-    let mut stdout = io::stdout();
-    
-    let mut unified_elements = canvas.unify_elements();
-    unified_elements.sort_by_key(|part| (part.position.1, part.position.0));
-    
-    for location in cleared_coords.iter() {
-        queue!(stdout, MoveTo(location.0, location.1), PrintStyledContent(" ".to_string().with(Color::Reset))).unwrap();
+    println!("Food: {}", canvas.interactable.iter().filter(|part| part.element == components::Element::Food).count());
+    if let Some(path) = &simulation.config.save {
+        simulation.snapshot().save(path)?;
     }
-
-    for part in unified_elements.iter() {
-        if *SHOW_STATS.read().unwrap() && part.position.1 == TERM_SIZE.read().unwrap().1 - 1 {
-            continue;
-        }
-        queue!(stdout, MoveTo(part.position.0, part.position.1),
-        PrintStyledContent(ELEMENT_VISUALS.read().unwrap()[&part.element].to_string().with(part.color))).unwrap();
-    
+    if let Some(recorder) = recorder.as_mut() {
+        recorder.flush()?;
     }
-    cleared_coords.clear();
-    stdout.flush().unwrap();
+    Ok(())
 }
 
-fn main() {
-    set_runtime_constants(args());
-    loop {
-        if !args().any(|arg| arg == "-t"){
-            *TERM_SIZE.write().unwrap() = crossterm::terminal::size().unwrap_or_else(|_| panic!("Cannot get terminal size"));
+fn run_terminal(config: Config) -> Result<(), Box<dyn Error>> {
+    let guard = TerminalGuard::enter(config.alternate_screen)?;
+    let mut simulation = Simulation::new(config);
+    let mut recorder = create_recorder(&simulation)?;
+    let mut renderer = CrosstermRenderer::new(simulation.size());
+    let mut stats = String::new();
+    // The first world already exists, it may have been resumed from a snapshot
+    let mut needs_restart = false;
+    'running: loop {
+        let size = if simulation.config.fixed_size {
+            simulation.config.size
+        } else {
+            crossterm::terminal::size()?
+        };
+        if needs_restart || simulation.size() != size {
+            // A terminal resized below the minimum would crash the creation of the canvas
+            let mut resized = simulation.config.clone();
+            resized.size = size;
+            resized.validate()?;
+            simulation.restart(size);
+            if let Some(recorder) = recorder.as_mut() {
+                recorder.reset(&simulation)?;
+            }
         }
-        // Prepare the terminal
-        let _ = enable_raw_mode();
-        execute!(io::stdout(), EnableLineWrap).unwrap();
-        let mut stdout = io::stdout();
-        stdout.queue(crossterm::cursor::Hide).unwrap();
-        stdout.queue(crossterm::terminal::Clear(crossterm::terminal::ClearType::All)).unwrap();
-        stdout.flush().unwrap();
-
-        // Create the canvas
-        let mut canvas: CanvasParts = create_canvas();
-        let mut state = components::CanvasState { iterations: 0, cleared_coords: Vec::new() }; //food_rate: 0
-        draw_canvas(&canvas, &mut state.cleared_coords);
+        renderer.reset(size);
+        draw_canvas(&mut renderer, &simulation.canvas, &simulation.config, &stats)?;
         let mut last_refresh = Instant::now();
 
-        *RESTART.write().unwrap() = false;
-        
-        while !*RESTART.read().unwrap() {
+        loop {
             let now = Instant::now();
-            pathfinder::head_handle(&mut canvas);
-            part_handler::spawner_handle(&mut canvas);
-            part_handler::handle_killed(&mut canvas.alive, &mut state.cleared_coords);
-            part_handler::spawn_food(&mut canvas);  
-
-            draw_canvas(&canvas, &mut state.cleared_coords);
-            state.iterations += 1;
+            simulation.step();
+            if let Some(recorder) = recorder.as_mut() {
+                recorder.record(&simulation)?;
+            }
 
-            handle_kb_input();
+            draw_canvas(&mut renderer, &simulation.canvas, &simulation.config, &stats)?;
+
+            match handle_kb_input(&mut simulation.config)? {
+                Input::Restart => {
+                    needs_restart = true;
+                    break;
+                }
+                Input::Quit => break 'running,
+                Input::Save => {
+                    let path = format!("dance-of-lines-{}-{}.json", simulation.config.seed, simulation.state.iterations);
+                    simulation.snapshot().save(Path::new(&path))?;
+                }
+                Input::Nothing => (),
+            }
+            if guard.stop.load(Ordering::Relaxed) {
+                break 'running;
+            }
 
             let elapsed = now.elapsed();
-            if elapsed < Duration::from_millis(*MIN_DELAY.read().unwrap()) {
-                sleep(Duration::from_millis(*MIN_DELAY.read().unwrap()) - elapsed);
+            let min_delay = simulation.config.min_delay;
+            if elapsed < Duration::from_millis(min_delay) {
+                sleep(Duration::from_millis(min_delay) - elapsed);
             }
-            if *SHOW_STATS.read().unwrap() && last_refresh.elapsed() > Duration::from_secs_f64(0.5) {
-            let mut stats_string = format!("Iterations:{}|FPS:{:.2?}|Creatures:{}|<S>:stats|<R>:restart|<C-^>:exit",
-                state.iterations, 1000.0 / (elapsed.as_secs_f64() + *MIN_DELAY.read().unwrap() as f64), canvas.alive.len());
-                stats_string.truncate(TERM_SIZE.read().unwrap().0 as usize - 1);
-                stats_string =  format!("{}{}", &stats_string, ELEMENT_VISUALS.read().unwrap()[&components::Element::Wall].to_string().repeat((TERM_SIZE.read().unwrap().0 - stats_string.len() as u16) as usize));
-                
-                queue!(stdout, MoveTo(0, TERM_SIZE.read().unwrap().1), PrintStyledContent(stats_string.with(Color::Rgb { r: 255, g: 60, b: 70 }))).unwrap();
-                
-                stdout.flush().unwrap();
+            // Shown with the next frame
+            if simulation.config.show_stats && last_refresh.elapsed() > Duration::from_secs_f64(0.5) {
+                stats = format!("Iterations:{}|FPS:{:.2?}|Creatures:{}|Lineages:{}|Seed:{}|<S>:stats|<R>:restart|<W>:save|<C-^>:exit",
+                    simulation.state.iterations, 1000.0 / (elapsed.as_secs_f64() + min_delay as f64), simulation.canvas.alive.len(), simulation.canvas.living_lineages(), simulation.config.seed);
+                stats.truncate(size.0 as usize - 1);
+                stats = format!("{}{}", &stats, simulation.config.visuals[&components::Element::Wall].to_string().repeat((size.0 - stats.len() as u16) as usize));
                 last_refresh = Instant::now();
             }
         }
-    }   
-}
\ No newline at end of file
+    }
+    if let Some(recorder) = recorder.as_mut() {
+        recorder.flush()?;
+    }
+    Ok(())
+}
+
+fn main() {
+    let config = cli::Cli::parse().into_config().unwrap_or_else(|err| {
+        eprintln!("error: {}", err);
+        exit(1);
+    });
+    let result = if let Some(path) = config.replay.clone() {
+        player::run_replay(&path, &config)
+    } else if config.headless {
+        run_headless(config).map_err(|err| err.into())
+    } else {
+        // The guard is dropped inside, so the error is printed to a restored terminal
+        run_terminal(config)
+    };
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        exit(1);
+    }
+}
@@ -0,0 +1,139 @@
+use std::fs;
+use std::path::Path;
+
+/// What a character of a map stands for.
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum MapCell {
+    Empty,
+    Wall,
+    Spawn,
+    Food,
+}
+
+/// A level read from a text file: `#` is a wall, `S` a spawner, `*` food and a space or `.` is empty.
+#[derive(Clone)]
+pub struct Map {
+    width: usize,
+    height: usize,
+    cells: Vec<MapCell>,
+}
+
+impl Map {
+    pub fn load(path: &Path) -> Result<Map, String> {
+        let content = fs::read_to_string(path).map_err(|err| format!("Cannot read {}: {}", path.display(), err))?;
+        Map::parse(&content).map_err(|err| format!("Invalid map {}: {}", path.display(), err))
+    }
+
+    pub fn parse(content: &str) -> Result<Map, String> {
+        let lines: Vec<&str> = content.lines().map(|line| line.trim_end()).collect();
+        // Trailing empty lines are not part of the map
+        let height = lines.iter().rposition(|line| !line.is_empty()).map_or(0, |last| last + 1);
+        let width = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+        if width == 0 || height == 0 {
+            return Err("the map is empty".to_string());
+        }
+
+        let mut cells = vec![MapCell::Empty; width * height];
+        for (y, line) in lines.iter().take(height).enumerate() {
+            for (x, character) in line.chars().enumerate() {
+                cells[y * width + x] = match character {
+                    '#' => MapCell::Wall,
+                    'S' => MapCell::Spawn,
+                    '*' => MapCell::Food,
+                    ' ' | '.' => MapCell::Empty,
+                    _ => return Err(format!("unknown character '{}' in line {}, column {}", character, y + 1, x + 1)),
+                };
+            }
+        }
+        Ok(Map { width, height, cells })
+    }
+
+    fn at(&self, x: usize, y: usize) -> MapCell {
+        self.cells[y * self.width + x]
+    }
+
+    /// Every non empty cell with its position in a world of `size`.
+    /// A scaled map is stretched over the whole world, walls grow with it while spawners and food stay single.
+    /// Otherwise the map is centered, cutting off what does not fit.
+    pub fn place(&self, size: (u16, u16), scale: bool) -> Vec<((u16, u16), MapCell)> {
+        let (world_width, world_height) = (size.0 as usize, size.1 as usize);
+        let mut placed = Vec::new();
+        if scale {
+            for y in 0..world_height {
+                for x in 0..world_width {
+                    if self.at(x * self.width / world_width, y * self.height / world_height) == MapCell::Wall {
+                        placed.push(((x as u16, y as u16), MapCell::Wall));
+                    }
+                }
+            }
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    let cell = self.at(x, y);
+                    if cell == MapCell::Spawn || cell == MapCell::Food {
+                        // The middle of the area the map cell is stretched over
+                        let position = (((2 * x + 1) * world_width / (2 * self.width)) as u16, ((2 * y + 1) * world_height / (2 * self.height)) as u16);
+                        placed.push((position, cell));
+                    }
+                }
+            }
+        } else {
+            let offset = ((world_width as i64 - self.width as i64) / 2, (world_height as i64 - self.height as i64) / 2);
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    let position = (x as i64 + offset.0, y as i64 + offset.1);
+                    let inside = (0..world_width as i64).contains(&position.0) && (0..world_height as i64).contains(&position.1);
+                    if inside && self.at(x, y) != MapCell::Empty {
+                        placed.push(((position.0 as u16, position.1 as u16), self.at(x, y)));
+                    }
+                }
+            }
+        }
+        placed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ragged_lines() {
+        let map = Map::parse("#S\n*\n\n").unwrap();
+        assert_eq!((map.width, map.height), (2, 2));
+        assert!(map.cells == [MapCell::Wall, MapCell::Spawn, MapCell::Food, MapCell::Empty]);
+    }
+
+    #[test]
+    fn rejects_unknown_characters_and_empty_maps() {
+        assert_eq!(Map::parse("#.\n#x").err().unwrap(), "unknown character 'x' in line 2, column 2");
+        assert_eq!(Map::parse(" \n\n").err().unwrap(), "the map is empty");
+    }
+
+    #[test]
+    fn places_centered_and_cut_off() {
+        let map = Map::parse("#S\n*.").unwrap();
+        let placed: Vec<((u16, u16), char)> = map.place((4, 4), false).into_iter().map(|(position, cell)| (position, symbol(cell))).collect();
+        assert_eq!(placed, [((1, 1), '#'), ((2, 1), 'S'), ((1, 2), '*')]);
+
+        // A map larger than the world is cut off around its middle
+        let placed: Vec<((u16, u16), char)> = map.place((1, 1), false).into_iter().map(|(position, cell)| (position, symbol(cell))).collect();
+        assert_eq!(placed, [((0, 0), '#')]);
+    }
+
+    #[test]
+    fn places_scaled() {
+        let map = Map::parse("#S\n*.").unwrap();
+        let placed: Vec<((u16, u16), char)> = map.place((4, 4), true).into_iter().map(|(position, cell)| (position, symbol(cell))).collect();
+        // Walls grow with the map, spawners and food stay in the middle of their area
+        assert_eq!(placed, [((0, 0), '#'), ((1, 0), '#'), ((0, 1), '#'), ((1, 1), '#'), ((3, 1), 'S'), ((1, 3), '*')]);
+    }
+
+    fn symbol(cell: MapCell) -> char {
+        match cell {
+            MapCell::Empty => '.',
+            MapCell::Wall => '#',
+            MapCell::Spawn => 'S',
+            MapCell::Food => '*',
+        }
+    }
+}
@@ -1,64 +1,76 @@
 use crossterm::style::Color;
+use rand::seq::SliceRandom;
 use rand::Rng;
 
-use crate::components::{CanvasParts, Creature, DirectionX, DirectionY, Element, Part, Species};
+use crate::components::{CanvasParts, CanvasState, Creature, Death, DirectionX, DirectionY, Element, Part, SimRng, pos_alteration_by_direction};
+use crate::config::Config;
+use crate::genome::Genome;
+use crate::species::{Species, SpeciesRegistry};
 
 
-fn check_collision(canvas: &Vec<Part>, position: (u16, u16)) -> bool {
-    canvas.iter().filter(|elem| elem.position == position).count() > 0
-}
-
-fn get_unused_color(creatures: &Vec<Creature>) -> Color {
+pub fn get_unused_color(creatures: &[Creature], rand_gen: &mut SimRng) -> Color {
     let mut color: Color = Color::Rgb { r: 0, g: 0, b: 0 };
     let mut color_used: bool = true;
-    let mut rand_gen = rand::thread_rng();
     while color_used {
         color = Color::Rgb { r: rand_gen.gen_range(0..255), g: rand_gen.gen_range(0..255), b: rand_gen.gen_range(0..255) };
-        color_used = creatures.iter().filter(|cret| cret.color == color).count() > 0;
+        color_used = creatures.iter().any(|cret| cret.color == color);
     }
     color
 }
 
-pub fn spawner_handle(canvas: &mut CanvasParts) {
+fn pick_species(species: &SpeciesRegistry, rand_gen: &mut SimRng) -> Species {
+    let total_weight = species.iter().map(|(_, _, settings)| settings.spawn_weight).sum::<u32>();
+    let mut roll = rand_gen.gen_range(0..total_weight);
+    for (species, _, settings) in species.iter() {
+        if roll < settings.spawn_weight {
+            return species;
+        }
+        roll -= settings.spawn_weight;
+    }
+    Species(0)
+}
+
+pub fn spawner_handle(canvas: &mut CanvasParts, config: &Config, species: &SpeciesRegistry, rand_gen: &mut SimRng) {
     // Collect the indices of active spawns
     let unused_spawns: Vec<usize> = canvas.interactable.iter_mut()
         .enumerate()
         .filter(|(_, elem)| elem.element == Element::Spawn && 
-                canvas.alive.iter()
-                .filter(|creature| creature.spawner_at == elem.position).count() == 0)
+                !canvas.alive.iter()
+                .any(|creature| creature.spawner_at == Some(elem.position)))
         .map(|(index, elem)|  {elem.color = Color::Rgb { r: 10, g: 255, b: 10 }; index})
         .collect();
 
-    let mut rand_gen = rand::thread_rng();
     let active_spawn_count = unused_spawns.len();
 
-    let x: u64 = rand_gen.gen_range(0..=1000);
-    if x < 10*active_spawn_count as u64 {
+    if active_spawn_count > 0 && rand_gen.gen_bool((config.spawn_chance * active_spawn_count as f64).min(1.0)) {
         let index = unused_spawns[rand_gen.gen_range(0..active_spawn_count)];
-        let pos: (u16, u16, (DirectionX, DirectionY)) = match x % 4 {
+        let direction = match rand_gen.gen_range(0..4) {
             // 0: up 1: right 2: down 3: left
-            0 => (canvas.interactable[index].position.0, canvas.interactable[index].position.1 - 1, (DirectionX::None, DirectionY::Up)),
-            1 => (canvas.interactable[index].position.0 + 1, canvas.interactable[index].position.1, (DirectionX::Right,DirectionY::None)),
-            2 => (canvas.interactable[index].position.0, canvas.interactable[index].position.1 + 1, (DirectionX::None, DirectionY::Down)),
-            3 => (canvas.interactable[index].position.0 - 1, canvas.interactable[index].position.1, (DirectionX::Left, DirectionY::None)),
-            _ => (1, 1, (DirectionX::None, DirectionY::Down)),
+            0 => (DirectionX::None, DirectionY::Up),
+            1 => (DirectionX::Right, DirectionY::None),
+            2 => (DirectionX::None, DirectionY::Down),
+            _ => (DirectionX::Left, DirectionY::None),
         };
-        
-        if !check_collision(&canvas.environment, (pos.0, pos.1)) {
-            let color = get_unused_color(&canvas.alive);
-            match x % 10 {
-                0  => canvas.add_creature((pos.0, pos.1), color, pos.2, Species::DetachedSnake, canvas.interactable[index].position),
-                1 => canvas.add_creature((pos.0, pos.1), color, pos.2, Species::Wesp, canvas.interactable[index].position),
-                _ => canvas.add_creature((pos.0, pos.1), color, pos.2, Species::NormalSnake, canvas.interactable[index].position),
-            }
+        let spawner = canvas.interactable[index].position;
+        let (x, y) = pos_alteration_by_direction(Some(&direction.0), Some(&direction.1), &spawner, canvas.wrap_size());
+        // Spawners on the edge of a world without border walls have no room on that side
+        let inside = (0..canvas.size.0 as i32).contains(&x) && (0..canvas.size.1 as i32).contains(&y);
+        let pos = (x as u16, y as u16, direction);
+
+        if inside && !canvas.grid.at((pos.0, pos.1)).contains(&Element::Wall) {
+            let color = get_unused_color(&canvas.alive, rand_gen);
+            let picked = pick_species(species, rand_gen);
+            let head = Part { element: species.behavior(picked).head(), position: (pos.0, pos.1), color };
+            let spawner_at = Some(canvas.interactable[index].position);
+            // Respawns continue the line of the longest surviving creatures
+            let genome = match canvas.gene_pool.pick(picked, rand_gen) {
+                Some(genome) => genome.mutate(config.mutation_rate, rand_gen),
+                None => Genome::new(species.behavior(picked), species.settings(picked), &species.elements()),
+            };
+            canvas.add_creature(Creature::new(head, pos.2, picked, spawner_at, canvas.lineages, genome, species.settings(picked)));
+            canvas.lineages += 1;
             canvas.interactable[index].color = Color::Rgb { r: 10, g: 100, b: 10 };
-            canvas.alive.sort_by(|a, b| {
-                match (a.species, b.species) {
-                    (_, Species::Wesp) => std::cmp::Ordering::Less,
-                    (Species::Wesp, _) => std::cmp::Ordering::Greater,
-                    _ => std::cmp::Ordering::Equal,
-                }
-            });   
+            canvas.alive.sort_by_key(|creature| species.behavior(creature.species).is_predator());
         }
     }
     
@@ -66,47 +78,100 @@ pub fn spawner_handle(canvas: &mut CanvasParts) {
 }
 
 
+/// Color of an offspring, close enough to `color` to tell the family apart.
+fn mutate_color(color: Color, rand_gen: &mut SimRng) -> Color {
+    let mut mutate = |channel: u8| (channel as i16 + rand_gen.gen_range(-32..=32)).clamp(0, 255) as u8;
+    match color {
+        Color::Rgb { r, g, b } => Color::Rgb { r: mutate(r), g: mutate(g), b: mutate(b) },
+        other => other,
+    }
+}
 
-pub fn handle_killed(creatures: &mut Vec<Creature>, cleared_coords: &mut Vec<(u16, u16)>) {
-    //let killed_creatures: Vec<usize> = creatures.iter().enumerate().filter(|(_, elem)| elem.killed).map(|(index, _)| index).collect::<Vec<_>>();
+/// Lets every creature that can breed lay an offspring on a free position next to its head.
+/// Parent and offspring share the energy of the parent, and the parent loses half of its length.
+pub fn breed_handle(canvas: &mut CanvasParts, config: &Config, species: &SpeciesRegistry, rand_gen: &mut SimRng) {
+    let mut directions = [(DirectionX::None, DirectionY::Up), (DirectionX::Right, DirectionY::Up), (DirectionX::Right, DirectionY::None),
+                          (DirectionX::Right, DirectionY::Down), (DirectionX::None, DirectionY::Down), (DirectionX::Left, DirectionY::Down),
+                          (DirectionX::Left, DirectionY::None), (DirectionX::Left, DirectionY::Up)];
+    let mut offspring: Vec<Creature> = Vec::new();
+    let wrap = canvas.wrap_size();
+
+    for parent in canvas.alive.iter_mut() {
+        let settings = species.settings(parent.species);
+        if !parent.can_breed(settings) {
+            continue;
+        }
+        directions.shuffle(rand_gen);
+        let head_position = parent.parts[0].position;
+        let free = directions.iter().map(|direction| (pos_alteration_by_direction(Some(&direction.0), Some(&direction.1), &head_position, wrap), *direction))
+            .find(|((x, y), _)| *x >= 0 && *y >= 0 && *x < canvas.size.0 as i32 && *y < canvas.size.1 as i32
+                  && !canvas.grid.is_occupied((*x as u16, *y as u16))
+                  && !offspring.iter().any(|child| child.parts[0].position == (*x as u16, *y as u16)));
+        let Some(((x, y), direction)) = free else {
+            continue;
+        };
+
+        let head = Part { element: species.behavior(parent.species).head(), position: (x as u16, y as u16), color: mutate_color(parent.color, rand_gen) };
+        let genome = parent.genome.mutate(config.mutation_rate, rand_gen);
+        let mut child = Creature::new(head, direction, parent.species, None, parent.lineage, genome, settings);
+        child.energy = parent.energy / 2;
+        parent.energy -= child.energy;
+        parent.length = parent.parts.len() / 2;
+        offspring.push(child);
+    }
+
+    if !offspring.is_empty() {
+        offspring.into_iter().for_each(|child| canvas.add_creature(child));
+        canvas.alive.sort_by_key(|creature| species.behavior(creature.species).is_predator());
+    }
+}
+
+/// Trims creatures to their length and removes killed ones a few parts per iteration.
+/// A removed head is reported in `state.deaths`.
+pub fn handle_killed(canvas: &mut CanvasParts, state: &mut CanvasState, species: &SpeciesRegistry) {
     let mut to_remove: Vec<usize> = Vec::new();
-    for (index, creature) in creatures.iter_mut().enumerate().rev() {
+    for index in (0..canvas.alive.len()).rev() {
         
-        if creature.species == Species::DetachedSnake && creature.parts.len() > 20 {
-            cleared_coords.push(creature.parts[1].position); //0 Is head, 1 is oldes part
-            creature.parts.remove(1);
-        } else if creature.species == Species::Wesp && creature.parts.len() > 8 {
-            cleared_coords.push(creature.parts[1].position); //
-            cleared_coords.push(creature.parts[2].position);
-            creature.parts.remove(1); //0 Is head, 1 is oldes part, tow parts are removed
-            creature.parts.remove(1);
+        let length = canvas.alive[index].length.max(1);
+        while canvas.alive[index].parts.len() > length {
+            canvas.remove_creature_part(index, 1); //0 Is head, 1 is oldes part
+        }
+        if canvas.alive[index].starved(species.settings(canvas.alive[index].species)) {
+            canvas.alive[index].killed = true;
         }
 
-        if creature.killed {
+        if canvas.alive[index].killed {
             for _ in 0..=3 {
-                if creature.parts.len() == 0 {
+                if canvas.alive[index].parts.is_empty() {
+                    let creature = &canvas.alive[index];
+                    canvas.gene_pool.record(creature.species, creature.age, &creature.genome);
                     to_remove.push(index);
                     break;
                 }
-                cleared_coords.push(creature.parts[0].position);
-                creature.parts.remove(0);
+                let part = canvas.remove_creature_part(index, 0);
+                let creature = &canvas.alive[index];
+                if part.element == species.behavior(creature.species).head() {
+                    state.deaths.push(Death { position: part.position, species: creature.species, lineage: creature.lineage, age: creature.age });
+                }
             }
         }
     }
     // It is bad to remove elements from a vector while iterating over it
-    to_remove.iter().for_each(|x| {creatures.remove(*x);});
+    to_remove.iter().for_each(|x| {canvas.alive.remove(*x);});
 }
 
 
 
-pub fn spawn_food(canvas: &mut CanvasParts) {
-    let mut rng = rand::thread_rng();
+pub fn spawn_food(canvas: &mut CanvasParts, config: &Config, rng: &mut SimRng) {
 
-    if rng.gen_bool(0.30){
-        let mut pos: (u16, u16) = (rng.gen_range(1..crate::TERM_SIZE.read().unwrap().0 - 1), rng.gen_range(1..crate::TERM_SIZE.read().unwrap().1 - 1));
-        while check_collision(&canvas.unify_elements().iter().map(|part| **part).collect(), pos) {
-            pos = (rng.gen_range(1..crate::TERM_SIZE.read().unwrap().0 - 1), rng.gen_range(1..crate::TERM_SIZE.read().unwrap().1 - 1));
+    if rng.gen_bool(config.food_probability){
+        // Without walls the edges are free as well
+        let (low, high) = if canvas.wrap { (0, canvas.size) } else { (1, (canvas.size.0 - 1, canvas.size.1 - 1)) };
+        // A crowded world may have no free position left, then no food appears this time
+        let free = (0..100).map(|_| (rng.gen_range(low..high.0), rng.gen_range(low..high.1)))
+            .find(|pos| !canvas.grid.is_occupied(*pos));
+        if let Some(pos) = free {
+            canvas.add_element(Element::Food, pos, Some(get_unused_color(&canvas.alive, rng)), None);
         }
-        canvas.add_element(Element::Food, pos, Some(get_unused_color(&canvas.alive)), None);
     }
 }
\ No newline at end of file
@@ -1,165 +1,169 @@
 
-use crate::components::{DirectionX, DirectionY, Element, Part, Species, directions_to_check, pos_alteration_by_direction};
-use crate::CanvasParts;
-use std::thread;
-use std::sync::mpsc;
-
-fn snake_path_match(elem:Element) -> i64 {
-    match elem {
-        Element::Wall | Element::Spawn => -100,
-        Element::BodyPartHori | Element::BodyPartVert | Element::WespBody | Element::WespHead |
-        Element::BodyPartLeftLean | Element::BodyPartRightLean | Element::BodyPartHead => -100,
-        Element::Food => 10,
-    }
+use crate::components::{CanvasParts, Creature, DirectionX, DirectionY, Part, directions_to_check, pos_alteration_by_direction};
+use crate::spatial::Sight;
+use crate::genome::Genome;
+use crate::species::SpeciesRegistry;
+use rayon::prelude::*;
+use rayon::ThreadPool;
+use std::collections::HashMap;
+
+/// Position to move to, direction walked and the value of the path behind it
+type PathData = (i32, i32, (DirectionX, DirectionY), i64);
+
+/// Position searched from, direction the creature is facing there and depth left to search
+type SearchKey = ((u16, u16), (DirectionX, DirectionY), u8);
+
+/// Everything a search for a single move needs, the same positions are reached over many paths so results are remembered
+struct PathSearch<'a> {
+    genome: &'a Genome,
+    sight: Sight<'a>,
+    fov: isize,
+    /// Size to wrap positions around, none if the world has edges
+    wrap: Option<(u16, u16)>,
+    memo: HashMap<SearchKey, PathData>,
 }
 
-fn wesp_path_match(elem:Element) -> i64 {
-    match elem {
-        Element::Wall | Element::Spawn | Element::Food => -100,
-        Element::WespBody => -20,
-        Element::BodyPartHead => 30,
-        _ => 1,
+fn recursive_colision_check(search: &mut PathSearch, position: &(u16, u16), direction: &(DirectionX, DirectionY), iterations_left: u8) -> PathData {
+    if let Some(path_data) = search.memo.get(&(*position, *direction, iterations_left)) {
+        return *path_data;
     }
-}
-
-
-fn snake_colision_matcher(parts_in_sight: &Vec<Part>, position: &(u16, u16), _: &CanvasParts) -> (bool, Option<Vec<usize>>) {
-    let colision = parts_in_sight.iter().any(|elem| elem.position == (position.0 as u16, position.1 as u16) && elem.element != Element::Food);
-    (colision, None)
-}
-
-fn wesp_colision_matcher(parts_in_sight: &Vec<Part>, position: &(u16, u16), canvas: &CanvasParts) -> (bool, Option<Vec<usize>>) {
-    let mut wesp_kills: Vec<usize> = Vec::new();
-    let mut colision = false;
-    for part in parts_in_sight.iter() {
-        if part.position == (position.0 as u16, position.1 as u16) && part.element == Element::BodyPartHead{
-            wesp_kills.push(canvas.alive.iter().filter(|creature| creature.parts.len() > 0).position(|creature| creature.parts[0].position == (position.0 as u16, position.1 as u16)).unwrap());
-        } else if part.position == (position.0 as u16, position.1 as u16) && part.element == Element::Food {
-            colision = true;
-        }
-    };
-    (colision, Some(wesp_kills))
-}
-
-fn recursive_colision_check(path_to_match: fn(Element) -> i64, parts_in_sight: &Vec<Part>, fov: isize, position: &(u16, u16),
-                             direction: &(DirectionX, DirectionY), iterations_left: u8, iterations_passed: u32) -> (i32, i32, (DirectionX, DirectionY), i64){
     // Position to check x, Position to check y, Direction to walk, Vale of Direction
-    let mut dyn_pos_res: Vec<(i32, i32, (DirectionX, DirectionY), i64)> = Vec::new();
+    let mut dyn_pos_res: Vec<PathData> = Vec::new();
 
 
     // Create a vector of tuples with the possible positions and their values(liklihood of beeing chosen)
-    for direction_to_check in directions_to_check(&direction, fov).iter() {
-        let (dir_x, dir_y): (DirectionX, DirectionY) = ((*direction_to_check).0, (*direction_to_check).1);
-        let (x, y): (i32, i32) = pos_alteration_by_direction(Some(&dir_x), Some(&dir_y), position);
+    for direction_to_check in directions_to_check(direction, search.fov).iter() {
+        let (dir_x, dir_y): (DirectionX, DirectionY) = (direction_to_check.0, direction_to_check.1);
+        let (x, y): (i32, i32) = pos_alteration_by_direction(Some(&dir_x), Some(&dir_y), position, search.wrap);
         dyn_pos_res.push((x, y, (dir_x, dir_y), 0));
     }
 
-    let (sender, receiver) = mpsc::channel();
-    let mut threads: Vec<thread::JoinHandle<()>> = Vec::new();
     // Iterate over the vector and check if the position is in sight, what value the sight has
-    for index in 0..dyn_pos_res.len() {
-        let (x, y, v_direction, mut val): (i32, i32, (DirectionX, DirectionY), i64) = dyn_pos_res[index].clone();
-        for part in parts_in_sight {
-            if part.position == (x as u16, y as u16) {
-                //When element in sight, part is element in sight
-                val = path_to_match(part.element)
-            } 
+    for candidate in dyn_pos_res.iter_mut() {
+        let (x, y, v_direction, _): PathData = *candidate;
+        //When element in sight, the latest placed element on the position decides
+        if let Some(element) = search.sight.at((x as u16, y as u16)).last() {
+            candidate.3 = search.genome.weight(*element);
         }
-        dyn_pos_res[index].3 = val;
 
         if iterations_left > 0 {
-            let sender = sender.clone();
-            let parts_in_sight = parts_in_sight.clone();
-            if *crate::MAX_THREADS.read().unwrap() != 0 && ((dyn_pos_res.len() as i32).pow(iterations_passed) as u32) < *crate::MAX_THREADS.read().unwrap() { // Test for 0 to avoid calculation time
-                threads.push(thread::spawn(move || {
-                    let t_val = recursive_colision_check(path_to_match, &parts_in_sight, fov, &(x as u16, y as u16), &v_direction, iterations_left - 1, iterations_passed +1).3;
-                    sender.send((t_val, index)).unwrap();
-                }));
-            } else {
-                dyn_pos_res[index].3 += recursive_colision_check(path_to_match, &parts_in_sight, fov, &(x as u16, y as u16), &v_direction, iterations_left - 1, iterations_passed +1).3;
-            }
+            candidate.3 += recursive_colision_check(search, &(x as u16, y as u16), &v_direction, iterations_left - 1).3;
         }
     }
 
-   
-    for _ in 0..(threads.len()) {
-        let thread_res = receiver.recv().unwrap();
-        dyn_pos_res[thread_res.1].3 += thread_res.0;
-    }
-
     
-    dyn_pos_res.sort_by(|a, b| b.3.cmp(&a.3));
+    dyn_pos_res.sort_by_key(|b| std::cmp::Reverse(b.3));
+    search.memo.insert((*position, *direction, iterations_left), dyn_pos_res[0]);
     dyn_pos_res[0]
     /*In this example, the sort_by method is used to sort the vector data. 
     The closure provided to sort_by compares tuples (i32, i32, u32) based on the third element (u32).
     b.2.cmp(&a.2) compares the third element of b and a (in reverse order because we want the highest element first). */
 }
 
-pub fn head_handle(canvas: &mut CanvasParts) {
-    //Todo: Reomve or improve threading in this function!!
-    // Find all elements that head can see
+fn find_head(creature: &Creature, species: &SpeciesRegistry) -> Option<Part> {
+    let head = species.behavior(creature.species).head();
+    creature.parts.first().filter(|part| part.element == head).copied()
+}
+
+/// Searches the best next move of the creature at `index`, only reading the canvas.
+fn find_path(canvas: &CanvasParts, species: &SpeciesRegistry, index: usize) -> PathData {
+    let creature = &canvas.alive[index];
+    let genome = &creature.genome;
+    let head = find_head(creature, species).expect("Only creatures with a head search a path");
+    let mut search = PathSearch {
+        genome,
+        // Only the cells around the head are looked up
+        sight: Sight::new(&canvas.grid, head.position, genome.sight_radius),
+        fov: genome.fov,
+        wrap: canvas.wrap_size(),
+        memo: HashMap::new(),
+    };
+    recursive_colision_check(&mut search, &head.position, &creature.curent_direction, genome.sight_radius as u8)
+}
+
+/// Moves every creature as far as its speed allows.
+/// The paths of one round are searched in parallel on `pool`, if any, and applied one after another.
+/// Predators search in a second wave, after the moves of their prey were applied.
+pub fn head_handle(canvas: &mut CanvasParts, species: &SpeciesRegistry, pool: Option<&ThreadPool>) {
     let mut foreign_changes: Vec<usize> = Vec::new();
 
-    let mut cloned_canvas = canvas.clone();
-    for (index, creature) in canvas.alive.iter_mut().enumerate() {
-        
-        let (fov, speed, sight_radius): (isize, u8, i32) = match creature.species {
-            Species::DetachedSnake => (2, 1, 4),
-            Species::Wesp => (1, 2, 4),
-            _ => (1, 1, 4),
-        };
-        let (eveluation_fn, colision_fn) = match creature.species {
-            Species::DetachedSnake | Species::NormalSnake => (
-                snake_path_match as fn(Element) -> i64,
-                snake_colision_matcher as fn(&Vec<Part>, &(u16, u16), &CanvasParts) -> (bool, Option<_>)
-            ),
-            Species::Wesp => (
-                wesp_path_match as fn(Element) -> i64,
-                wesp_colision_matcher as fn(&Vec<Part>, &(u16, u16), &CanvasParts) -> (bool, Option<_>)
-            ),
+    let max_speed = canvas.alive.iter().map(|creature| species.settings(creature.species).speed).max().unwrap_or(0);
+    for (round, predators) in (0..max_speed).flat_map(|round| [(round, false), (round, true)]) {
+        let moving: Vec<usize> = (0..canvas.alive.len())
+            .filter(|index| species.settings(canvas.alive[*index].species).speed > round && find_head(&canvas.alive[*index], species).is_some()
+                    && species.behavior(canvas.alive[*index].species).is_predator() == predators)
+            .collect();
+
+        // All searches of a wave see the canvas as it was at the start of the wave, which keeps the result independent of the pool
+        let searched_canvas: &CanvasParts = canvas;
+        let paths: Vec<PathData> = match pool {
+            Some(pool) => pool.install(|| moving.par_iter().map(|index| find_path(searched_canvas, species, *index)).collect()),
+            None => moving.iter().map(|index| find_path(searched_canvas, species, *index)).collect(),
         };
 
-        for _ in 0..speed {
-            let head = creature.parts.iter().filter(|elem| elem.element == Element::BodyPartHead || elem.element == Element::WespHead).next();
-            if head.is_none() {
-                continue;
-            }
-            let head = head.unwrap().clone(); // Clone the head to avoid borrowing issues
-            // Concartination of all parts in sight
-            let parts_in_sight: Vec<Part> = cloned_canvas.unify_elements().iter()
-                    .filter(|elem| 
-                    (elem.position.0 as i64 - head.position.0 as i64).pow(2) + 
-                    (elem.position.1 as i64 - head.position.1 as i64).pow(2) <= sight_radius.pow(2) as i64)
-                    .map(|elem| **elem)
-                    .collect::<Vec<_>>();   
-
-           
-            let path_data = recursive_colision_check(eveluation_fn, &parts_in_sight, fov,
-                            &head.position, &creature.curent_direction , sight_radius as u8, 0);   
-
-            // Check colisions of new position
-            let (colision, opt_foreign_changes) = colision_fn(&parts_in_sight, &(path_data.0 as u16, path_data.1 as u16), &cloned_canvas);
-            match opt_foreign_changes {
-                Some(mut x) => foreign_changes.append(&mut x),
-                None => (),
+        for (index, path_data) in moving.into_iter().zip(paths) {
+            let creature = &canvas.alive[index];
+            let head = find_head(creature, species).expect("Heads are only removed after all moves");
+            let behavior = species.behavior(creature.species);
+            let new_position = (path_data.0 as u16, path_data.1 as u16);
+
+            // Check colisions of new position against the moves applied so far
+            let sight = Sight::new(&canvas.grid, head.position, creature.genome.sight_radius);
+            let (colision, opt_foreign_changes) = behavior.colision(&sight, &new_position, canvas);
+            let mut meals = 0;
+            if let Some(mut x) = opt_foreign_changes {
+                meals += x.len();
+                foreign_changes.append(&mut x);
             }
 
             // Move the head to the new position, spawn a new body part and update the direction
-            creature.move_to((path_data.0 as u16, path_data.1 as u16), path_data.2, colision);
-            cloned_canvas.alive[index] = creature.clone(); // Update cloned canvass
-
-            // Clean up food
-            let mut to_remove: Vec<usize> = Vec::new();
-            // Iterate over a mutable reference to the canvas vector
-            for (index, element) in canvas.interactable.iter_mut().enumerate() {
-                if element.position == (path_data.0 as u16, path_data.1 as u16) && element.element == Element::Food {
-                    to_remove.push(index);
-                } 
+            let body_part = behavior.body_part(path_data.2);
+            canvas.move_creature(index, new_position, path_data.2, body_part, colision);
+
+            // Clean up food, it only feeds creatures that survived the move onto it
+            if canvas.remove_food(new_position) && !colision {
+                meals += 1;
+            }
+
+            let settings = species.settings(canvas.alive[index].species);
+            let creature = &mut canvas.alive[index];
+            if !creature.killed {
+                creature.spend_energy(settings);
+                (0..meals).for_each(|_| creature.feed(settings));
             }
-            
-            // Remove elements from the canvas vector based on the condition
-            to_remove.iter().for_each(|x| {canvas.interactable.remove(*x);});
         }
     }
     foreign_changes.iter().for_each(|x| {canvas.alive[*x].killed = true;});
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crossterm::style::Color;
+
+    /// A creature of `name` made of its head only, seeing as far as `sight_radius`
+    fn creature(species: &SpeciesRegistry, name: &str, position: (u16, u16), direction: (DirectionX, DirectionY), sight_radius: i32) -> Creature {
+        let picked = species.by_name(name).unwrap();
+        let (behavior, settings) = (species.behavior(picked), species.settings(picked));
+        let mut genome = Genome::new(behavior, settings, &species.elements());
+        genome.sight_radius = sight_radius;
+        let head = Part { element: behavior.head(), position, color: Color::Red };
+        Creature::new(head, direction, picked, None, 0, genome, settings)
+    }
+
+    #[test]
+    fn predators_aim_at_where_their_prey_is_now() {
+        let config = Config::default();
+        let species = SpeciesRegistry::from_config(&config).unwrap();
+        let mut canvas = CanvasParts::new((12, 10), false);
+        // The wasp can not see the snake before it moves next to it
+        canvas.add_creature(creature(&species, "snake", (5, 5), (DirectionX::Right, DirectionY::None), 1));
+        canvas.add_creature(creature(&species, "wesp", (7, 4), (DirectionX::Left, DirectionY::None), 2));
+
+        head_handle(&mut canvas, &species, None);
+        assert_eq!(canvas.alive[0].parts[0].position, (6, 5));
+        assert!(canvas.alive[0].killed);
+        assert!(!canvas.alive[1].killed);
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use crossterm::{event::{poll, read, Event, KeyCode, KeyEvent, KeyModifiers}, style::Color};
+use dance_of_lines::recording::{apply_frame, Cell, RecordedDeath, Recording};
+use dance_of_lines::render::Renderer;
+use dance_of_lines::Config;
+
+use crate::terminal::{CrosstermRenderer, TerminalGuard};
+
+/// Frames skipped by a single seek
+const SEEK_STEP: usize = 100;
+
+/// What the user asked the player for with the latest key press
+enum PlayerInput {
+    Nothing,
+    TogglePause,
+    Step,
+    Seek(isize),
+    Faster,
+    Slower,
+    Quit,
+}
+
+fn handle_player_input() -> io::Result<PlayerInput> {
+    if !poll(Duration::from_millis(0))? {
+        return Ok(PlayerInput::Nothing);
+    }
+    let input = match read()? {
+        Event::Key(KeyEvent{code: KeyCode::Char('c'), modifiers: KeyModifiers::CONTROL, kind: _, state: _}) => PlayerInput::Quit,
+        Event::Key(KeyEvent{code, modifiers: _, kind: _, state: _}) => match code {
+            KeyCode::Char('q') => PlayerInput::Quit,
+            KeyCode::Char(' ') => PlayerInput::TogglePause,
+            KeyCode::Char('.') => PlayerInput::Step,
+            KeyCode::Right => PlayerInput::Seek(SEEK_STEP as isize),
+            KeyCode::Left => PlayerInput::Seek(-(SEEK_STEP as isize)),
+            KeyCode::Home => PlayerInput::Seek(isize::MIN),
+            KeyCode::End => PlayerInput::Seek(isize::MAX),
+            KeyCode::Char('+') => PlayerInput::Faster,
+            KeyCode::Char('-') => PlayerInput::Slower,
+            _ => PlayerInput::Nothing,
+        },
+        _ => PlayerInput::Nothing,
+    };
+    Ok(input)
+}
+
+/// Plays a recording frame by frame, the simulation itself is not run.
+struct Player {
+    recording: Recording,
+    /// Frame whose changes are on screen
+    index: usize,
+    cells: HashMap<(u16, u16), Cell>,
+    /// Row of the status line, it covers whatever the recording shows there
+    status_row: u16,
+}
+
+impl Player {
+    /// Moves on to the next frame, the last one stays on screen.
+    fn advance(&mut self) {
+        if self.index + 1 >= self.recording.frames.len() {
+            return;
+        }
+        self.index += 1;
+        apply_frame(&mut self.cells, &self.recording.frames[self.index]);
+    }
+
+    fn seek(&mut self, frames: isize) {
+        let last = self.recording.frames.len() - 1;
+        self.index = self.index.saturating_add_signed(frames).min(last);
+        self.cells = self.recording.cells_at(self.index);
+    }
+
+    /// The latest death up to the current frame, with the iteration it happened in
+    fn last_death(&self) -> Option<(u128, &RecordedDeath)> {
+        self.recording.frames[..=self.index].iter().rev()
+            .find_map(|frame| frame.deaths.last().map(|death| (frame.iteration, death)))
+    }
+
+    /// Draws the current frame with the status line.
+    fn draw(&self, renderer: &mut impl Renderer, paused: bool, speed: f64) -> io::Result<()> {
+        renderer.clear();
+        for (position, cell) in self.cells.iter() {
+            renderer.draw_cell(*position, *cell);
+        }
+
+        let frame = &self.recording.frames[self.index];
+        let death = match self.last_death() {
+            Some((iteration, death)) => format!("last death:{} of lineage {} aged {} at {},{} in {}",
+                death.species, death.lineage, death.age, death.position.0, death.position.1, iteration),
+            None => "no deaths".to_string(),
+        };
+        let status = format!("Frame:{}/{}|Iteration:{}|Speed:x{}|{}|{}|<Space>:pause|<.>:step|<Left/Right>:seek|<+/->:speed|<Q>:exit",
+            self.index, self.recording.frames.len() - 1, frame.iteration, speed, if paused { "paused" } else { "playing" }, death);
+        let width = renderer.size().0 as usize;
+        renderer.draw_text((0, self.status_row), &format!("{:width$}", status), Color::Rgb { r: 255, g: 60, b: 70 });
+        renderer.present()
+    }
+}
+
+/// Plays the recording at `path` in the terminal, starting at the speed of `config.min_delay`.
+pub fn run_replay(path: &Path, config: &Config) -> Result<(), Box<dyn Error>> {
+    let recording = Recording::load(path)?;
+    let terminal_size = crossterm::terminal::size()?;
+    let world_size = recording.frames.iter().map(|frame| frame.size).fold((0, 0), |max, size| (max.0.max(size.0), max.1.max(size.1)));
+    if world_size.0 > terminal_size.0 || world_size.1 > terminal_size.1 {
+        return Err(format!("The recorded world of {}x{} does not fit into the terminal of {}x{}", world_size.0, world_size.1, terminal_size.0, terminal_size.1).into());
+    }
+
+    let guard = TerminalGuard::enter(config.alternate_screen)?;
+    let mut renderer = CrosstermRenderer::new(terminal_size);
+    let mut player = Player { recording, index: 0, cells: HashMap::new(), status_row: terminal_size.1 - 1 };
+    player.cells = player.recording.cells_at(0);
+
+    let mut paused = false;
+    let mut speed: f64 = 1.0;
+    loop {
+        let now = Instant::now();
+        player.draw(&mut renderer, paused, speed)?;
+
+        match handle_player_input()? {
+            PlayerInput::Quit => return Ok(()),
+            PlayerInput::TogglePause => paused = !paused,
+            PlayerInput::Step => {
+                paused = true;
+                player.advance();
+            }
+            PlayerInput::Seek(frames) => player.seek(frames),
+            PlayerInput::Faster => speed = (speed * 2.0).min(64.0),
+            PlayerInput::Slower => speed = (speed / 2.0).max(1.0 / 8.0),
+            PlayerInput::Nothing => (),
+        }
+        if guard.stop.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        if !paused {
+            player.advance();
+        }
+
+        let delay = Duration::from_secs_f64(config.min_delay.max(1) as f64 / 1000.0 / speed);
+        let elapsed = now.elapsed();
+        if elapsed < delay {
+            sleep(delay - elapsed);
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Simulation;
+
+/// Written to the header, the player only reads its own version
+const RECORDING_VERSION: u32 = 1;
+
+use crate::render::canvas_cells;
+pub use crate::render::Cell;
+
+/// First line of a recording.
+#[derive(Serialize, Deserialize)]
+pub struct Header {
+    pub version: u32,
+    pub seed: u64,
+}
+
+/// A death as it is recorded, with the name of the species instead of its id.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RecordedDeath {
+    pub position: (u16, u16),
+    pub species: String,
+    pub lineage: u32,
+    pub age: u64,
+}
+
+/// The changes of one iteration, every line of a recording after the header holds one.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Frame {
+    pub iteration: u128,
+    /// Everything drawn before is gone, like after a restart
+    pub reset: bool,
+    pub size: (u16, u16),
+    /// Positions that show something new
+    pub drawn: Vec<((u16, u16), Cell)>,
+    /// Positions that became empty
+    pub cleared: Vec<(u16, u16)>,
+    pub deaths: Vec<RecordedDeath>,
+}
+
+/// Writes the changes of every iteration of a simulation to a file.
+pub struct Recorder {
+    writer: BufWriter<File>,
+    /// What was drawn after the last recorded iteration
+    cells: HashMap<(u16, u16), Cell>,
+}
+
+impl Recorder {
+    /// Starts a recording at `path` with the current world of `simulation` as first frame.
+    pub fn create(path: &Path, simulation: &Simulation) -> Result<Recorder, String> {
+        let file = File::create(path).map_err(|err| format!("Cannot create {}: {}", path.display(), err))?;
+        let mut recorder = Recorder { writer: BufWriter::new(file), cells: HashMap::new() };
+        recorder.write_line(&Header { version: RECORDING_VERSION, seed: simulation.config.seed })?;
+        recorder.reset(simulation)?;
+        Ok(recorder)
+    }
+
+    fn write_line<T: Serialize>(&mut self, value: &T) -> Result<(), String> {
+        serde_json::to_writer(&mut self.writer, value).map_err(|err| format!("Cannot write the recording: {}", err))?;
+        self.writer.write_all(b"\n").map_err(|err| format!("Cannot write the recording: {}", err))
+    }
+
+    /// Records the whole world again, after it was restarted.
+    pub fn reset(&mut self, simulation: &Simulation) -> Result<(), String> {
+        self.cells.clear();
+        self.write_frame(simulation, true)
+    }
+
+    /// Records what changed in the last iteration of `simulation`.
+    pub fn record(&mut self, simulation: &Simulation) -> Result<(), String> {
+        self.write_frame(simulation, false)
+    }
+
+    fn write_frame(&mut self, simulation: &Simulation, reset: bool) -> Result<(), String> {
+        let cells = visible_cells(simulation);
+        let mut drawn: Vec<((u16, u16), Cell)> = cells.iter()
+            .filter(|(position, cell)| self.cells.get(position) != Some(cell))
+            .map(|(position, cell)| (*position, *cell))
+            .collect();
+        let mut cleared: Vec<(u16, u16)> = self.cells.keys().filter(|position| !cells.contains_key(position)).copied().collect();
+        // Keeps recordings of the same run identical
+        drawn.sort_by_key(|(position, _)| (position.1, position.0));
+        cleared.sort_by_key(|position| (position.1, position.0));
+        let deaths = simulation.state.deaths.iter().map(|death| RecordedDeath {
+            position: death.position,
+            species: simulation.species.behavior(death.species).name().to_string(),
+            lineage: death.lineage,
+            age: death.age,
+        }).collect();
+
+        let frame = Frame { iteration: simulation.state.iterations, reset, size: simulation.size(), drawn, cleared, deaths };
+        self.write_line(&frame)?;
+        self.cells = cells;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), String> {
+        self.writer.flush().map_err(|err| format!("Cannot write the recording: {}", err))
+    }
+}
+
+/// What a frontend draws on every position, the part placed last wins.
+fn visible_cells(simulation: &Simulation) -> HashMap<(u16, u16), Cell> {
+    canvas_cells(&simulation.canvas, &simulation.config).collect()
+}
+
+/// A recording read back from a file.
+pub struct Recording {
+    pub header: Header,
+    pub frames: Vec<Frame>,
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> Result<Recording, String> {
+        let file = File::open(path).map_err(|err| format!("Cannot read {}: {}", path.display(), err))?;
+        let mut lines = BufReader::new(file).lines();
+        let invalid = |err: &dyn std::fmt::Display| format!("Invalid recording {}: {}", path.display(), err);
+
+        let header_line = lines.next().ok_or_else(|| invalid(&"the file is empty"))?.map_err(|err| invalid(&err))?;
+        let header: Header = serde_json::from_str(&header_line).map_err(|err| invalid(&err))?;
+        if header.version != RECORDING_VERSION {
+            return Err(format!("The recording {} has version {}, only version {} can be read", path.display(), header.version, RECORDING_VERSION));
+        }
+        let mut frames = Vec::new();
+        for line in lines {
+            let line = line.map_err(|err| invalid(&err))?;
+            frames.push(serde_json::from_str::<Frame>(&line).map_err(|err| invalid(&err))?);
+        }
+        if frames.is_empty() {
+            return Err(invalid(&"it holds no frames"));
+        }
+        Ok(Recording { header, frames })
+    }
+
+    /// Everything drawn after the frame at `index`, found by playing all frames up to it.
+    pub fn cells_at(&self, index: usize) -> HashMap<(u16, u16), Cell> {
+        let mut cells = HashMap::new();
+        for frame in self.frames.iter().take(index + 1) {
+            apply_frame(&mut cells, frame);
+        }
+        cells
+    }
+}
+
+/// Applies the changes of `frame` to what is drawn.
+pub fn apply_frame(cells: &mut HashMap<(u16, u16), Cell>, frame: &Frame) {
+    if frame.reset {
+        cells.clear();
+    }
+    for position in frame.cleared.iter() {
+        cells.remove(position);
+    }
+    cells.extend(frame.drawn.iter().copied());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn replayed_frames_show_the_recorded_world() {
+        let path = std::env::temp_dir().join(format!("dance-of-lines-recording-{}.jsonl", std::process::id()));
+        let config = Config { seed: 11, ..Config::default() };
+        config.validate().unwrap();
+        let mut simulation = Simulation::new(config);
+        let mut recorder = Recorder::create(&path, &simulation).unwrap();
+        for _ in 0..150 {
+            simulation.step();
+            recorder.record(&simulation).unwrap();
+        }
+        // A smaller world leaves nothing of the larger one behind
+        simulation.restart((40, 12));
+        recorder.reset(&simulation).unwrap();
+        let restarted: HashMap<(u16, u16), Cell> = canvas_cells(&simulation.canvas, &simulation.config).collect();
+        for _ in 0..150 {
+            simulation.step();
+            recorder.record(&simulation).unwrap();
+        }
+        recorder.flush().unwrap();
+        let recording = Recording::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let recording = recording.unwrap();
+
+        assert_eq!(recording.header.seed, 11);
+        assert_eq!(recording.frames.len(), 302);
+        assert!(recording.frames[0].reset && recording.frames[151].reset);
+        assert_eq!(recording.frames.iter().filter(|frame| frame.reset).count(), 2);
+        assert_eq!(recording.cells_at(151), restarted);
+        let last = recording.frames.len() - 1;
+        let expected: HashMap<(u16, u16), Cell> = canvas_cells(&simulation.canvas, &simulation.config).collect();
+        assert_eq!(recording.cells_at(last), expected);
+    }
+}
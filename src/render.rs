@@ -0,0 +1,369 @@
+use std::io;
+
+use crossterm::style::Color;
+use serde::Deserialize;
+
+use crate::components::{CanvasParts, Creature, Element};
+use crate::config::Config;
+
+/// What is drawn on a position: the glyph and its color
+pub type Cell = (char, Color);
+
+const BLANK: Cell = (' ', Color::Reset);
+
+/// How the bodies of snakes are drawn.
+#[derive(Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum BodyStyle {
+    /// Every segment shows the direction it was left in
+    Lines,
+    /// Every segment joins the ones before and after it, turns become corners
+    Connected,
+}
+
+/// Where frames are drawn to. Cells are drawn into the next frame, which is shown on `present`.
+pub trait Renderer {
+    /// Columns and rows that can be drawn on
+    fn size(&self) -> (u16, u16);
+    /// Positions outside of `size` are ignored
+    fn draw_cell(&mut self, position: (u16, u16), cell: Cell);
+    fn clear_cell(&mut self, position: (u16, u16));
+    /// Shows the frame drawn since the last call.
+    fn present(&mut self) -> io::Result<()>;
+
+    /// Empties the next frame to draw it from scratch.
+    fn clear(&mut self) {
+        let size = self.size();
+        for y in 0..size.1 {
+            for x in 0..size.0 {
+                self.clear_cell((x, y));
+            }
+        }
+    }
+
+    /// Writes `text` from `position` on, cut at the right edge.
+    fn draw_text(&mut self, position: (u16, u16), text: &str, color: Color) {
+        for (column, glyph) in (position.0..self.size().0).zip(text.chars()) {
+            self.draw_cell((column, position.1), (glyph, color));
+        }
+    }
+}
+
+/// Draws every part of `canvas` into the next frame of `renderer`, the part placed last wins.
+pub fn draw_canvas<R: Renderer + ?Sized>(renderer: &mut R, canvas: &CanvasParts, config: &Config) {
+    for (position, cell) in canvas_cells(canvas, config) {
+        renderer.draw_cell(position, cell);
+    }
+}
+
+/// How every part of `canvas` looks, in the order of `CanvasParts::unify_elements`.
+pub fn canvas_cells<'a>(canvas: &'a CanvasParts, config: &'a Config) -> impl Iterator<Item = ((u16, u16), Cell)> + 'a {
+    let placed = canvas.environment.iter().chain(canvas.interactable.iter())
+        .map(|part| (part.position, (config.visuals[&part.element], part.color)));
+    let bodies = canvas.alive.iter().flat_map(move |creature| creature.parts.iter().enumerate().map(move |(index, part)| {
+        let element = match config.body_style {
+            BodyStyle::Lines => part.element,
+            BodyStyle::Connected => connected_element(creature, index),
+        };
+        (part.position, (config.visuals[&element], part.color))
+    }));
+    placed.chain(bodies)
+}
+
+/// The element of the connected style for the part at `index`, joining its neighbours towards the tail and the head.
+fn connected_element(creature: &Creature, index: usize) -> Element {
+    let line = |element: Element| matches!(element, Element::BodyPartVert | Element::BodyPartHori | Element::BodyPartLeftLean | Element::BodyPartRightLean);
+    let part = &creature.parts[index];
+    if !line(part.element) {
+        return part.element;
+    }
+    // The oldest part comes right after the head and the newest one is followed by it,
+    // dying creatures lose their head first and start with their oldest part
+    let headless = line(creature.parts[0].element);
+    let towards_tail = (index > usize::from(!headless)).then(|| index - 1);
+    let towards_head = match index + 1 < creature.parts.len() {
+        true => Some(index + 1),
+        false => (!headless).then_some(0),
+    };
+    let side_of = |neighbour: usize| side(part.position, creature.parts[neighbour].position);
+    match (towards_tail.map(side_of), towards_head.map(side_of)) {
+        (Some(tail_side), Some(head_side)) => joint(tail_side, head_side),
+        (Some(end), None) | (None, Some(end)) => joint(end, (-end.0, -end.1)),
+        (None, None) => match part.element {
+            Element::BodyPartVert => Element::LineVert,
+            Element::BodyPartHori => Element::LineHori,
+            Element::BodyPartLeftLean => Element::LineLeftLean,
+            _ => Element::LineRightLean,
+        },
+    }
+}
+
+/// Direction from `from` to its neighbour `to`, larger steps only happen across the edges of a wrapping world
+fn side(from: (u16, u16), to: (u16, u16)) -> (i32, i32) {
+    let step = |from: u16, to: u16| match to as i32 - from as i32 {
+        delta if delta.abs() > 1 => -delta.signum(),
+        delta => delta,
+    };
+    (step(from.0, to.0), step(from.1, to.1))
+}
+
+/// The line element connecting the neighbours on sides `a` and `b`.
+fn joint(a: (i32, i32), b: (i32, i32)) -> Element {
+    let diagonal = |side: (i32, i32)| side.0 != 0 && side.1 != 0;
+    // Up left to down right, rows grow downwards
+    let lean = |side: (i32, i32)| if side.0 == side.1 { Element::LineRightLean } else { Element::LineLeftLean };
+    match (diagonal(a), diagonal(b)) {
+        (true, true) if a == (-b.0, -b.1) => lean(a),
+        (true, true) => Element::LineCross,
+        (true, false) => lean(a),
+        (false, true) => lean(b),
+        _ if a.0 == 0 && b.0 == 0 => Element::LineVert,
+        _ if a.1 == 0 && b.1 == 0 => Element::LineHori,
+        _ => {
+            let (vertical, horizontal) = if a.0 == 0 { (a, b) } else { (b, a) };
+            match (vertical.1 < 0, horizontal.0 > 0) {
+                (true, true) => Element::LineUpRight,
+                (true, false) => Element::LineUpLeft,
+                (false, true) => Element::LineDownRight,
+                (false, false) => Element::LineDownLeft,
+            }
+        }
+    }
+}
+
+/// Changed cells next to each other on one row, written with a single cursor move.
+pub struct Run {
+    pub start: (u16, u16),
+    /// Empty positions are blanks with the default color
+    pub cells: Vec<Cell>,
+}
+
+/// Two grids of cells: the front one as it is on screen, the back one as the next frame should look.
+/// Terminal renderers draw into the back grid and only write what differs from the front one.
+pub struct FrameBuffer {
+    size: (u16, u16),
+    front: Vec<Cell>,
+    back: Vec<Cell>,
+}
+
+impl FrameBuffer {
+    /// A buffer for an empty screen of `size`.
+    pub fn new(size: (u16, u16)) -> FrameBuffer {
+        let cells = size.0 as usize * size.1 as usize;
+        FrameBuffer { size, front: vec![BLANK; cells], back: vec![BLANK; cells] }
+    }
+
+    pub fn size(&self) -> (u16, u16) {
+        self.size
+    }
+
+    /// Forgets everything drawn, for a screen that was cleared.
+    pub fn reset(&mut self, size: (u16, u16)) {
+        *self = FrameBuffer::new(size);
+    }
+
+    /// Empties the back grid to draw the next frame from scratch.
+    pub fn clear(&mut self) {
+        self.back.fill(BLANK);
+    }
+
+    /// Positions outside of the screen are ignored
+    pub fn set(&mut self, position: (u16, u16), cell: Cell) {
+        if position.0 < self.size.0 && position.1 < self.size.1 {
+            self.back[position.1 as usize * self.size.0 as usize + position.0 as usize] = cell;
+        }
+    }
+
+    /// The cells that changed since the last call, row by row, after which the front grid matches the back one.
+    pub fn diff(&mut self) -> Vec<Run> {
+        let mut runs: Vec<Run> = Vec::new();
+        let width = self.size.0 as usize;
+        for (index, (front, back)) in self.front.iter_mut().zip(self.back.iter()).enumerate() {
+            if front == back {
+                continue;
+            }
+            *front = *back;
+            let position = ((index % width) as u16, (index / width) as u16);
+            match runs.last_mut() {
+                // Continues the run ending right before it on the same row
+                Some(run) if run.start.1 == position.1 && run.start.0 as usize + run.cells.len() == position.0 as usize => run.cells.push(*back),
+                _ => runs.push(Run { start: position, cells: vec![*back] }),
+            }
+        }
+        runs
+    }
+}
+
+/// Keeps frames in memory instead of showing them, for frontends without a terminal and to compare frames.
+pub struct GridRenderer {
+    buffer: FrameBuffer,
+    /// Frames presented so far
+    pub frames: u64,
+}
+
+impl GridRenderer {
+    pub fn new(size: (u16, u16)) -> GridRenderer {
+        GridRenderer { buffer: FrameBuffer::new(size), frames: 0 }
+    }
+
+    /// What the last presented frame shows on `position`, blanks outside of it
+    pub fn cell(&self, position: (u16, u16)) -> Cell {
+        let size = self.buffer.size;
+        if position.0 >= size.0 || position.1 >= size.1 {
+            return BLANK;
+        }
+        self.buffer.front[position.1 as usize * size.0 as usize + position.0 as usize]
+    }
+
+    /// The glyphs of the last presented frame, one string per row.
+    pub fn lines(&self) -> Vec<String> {
+        self.buffer.front.chunks(self.buffer.size.0.max(1) as usize)
+            .map(|row| row.iter().map(|(glyph, _)| glyph).collect())
+            .collect()
+    }
+}
+
+impl Renderer for GridRenderer {
+    fn size(&self) -> (u16, u16) {
+        self.buffer.size
+    }
+
+    fn draw_cell(&mut self, position: (u16, u16), cell: Cell) {
+        self.buffer.set(position, cell);
+    }
+
+    fn clear_cell(&mut self, position: (u16, u16)) {
+        self.buffer.set(position, BLANK);
+    }
+
+    fn present(&mut self) -> io::Result<()> {
+        self.buffer.front.clone_from(&self.buffer.back);
+        self.frames += 1;
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.buffer.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::{DirectionX, DirectionY, Part};
+    use crate::genome::Genome;
+    use crate::species::Species;
+    use crate::theme::Theme;
+
+    const RED: Color = Color::Rgb { r: 255, g: 0, b: 0 };
+
+    fn part(element: Element, position: (u16, u16)) -> Part {
+        Part { element, position, color: RED }
+    }
+
+    /// A snake kept like the simulation does: the head first, then the body from the tail on.
+    fn snake(head: (u16, u16), body: &[((u16, u16), Element)]) -> Creature {
+        let genome = Genome { weights: Vec::new(), fov: 1, sight_radius: 4, generation: 0 };
+        let settings = Config::default().species["snake"];
+        let mut creature = Creature::new(part(Element::BodyPartHead, head), (DirectionX::Right, DirectionY::None), Species(0), None, 0, genome, &settings);
+        creature.parts.extend(body.iter().map(|(position, element)| part(*element, *position)));
+        creature
+    }
+
+    /// A walled canvas of `size` drawn with the ascii theme
+    fn walled(size: (u16, u16)) -> (CanvasParts, Config) {
+        let mut canvas = CanvasParts::new(size, false);
+        for y in 0..size.1 {
+            for x in 0..size.0 {
+                if x == 0 || y == 0 || x == size.0 - 1 || y == size.1 - 1 {
+                    canvas.add_element(Element::Wall, (x, y), Some(RED), None);
+                }
+            }
+        }
+        (canvas, Config { visuals: Theme::Ascii.visuals(), ..Config::default() })
+    }
+
+    fn render(canvas: &CanvasParts, config: &Config) -> GridRenderer {
+        let mut renderer = GridRenderer::new(canvas.size);
+        draw_canvas(&mut renderer, canvas, config);
+        renderer.present().unwrap();
+        renderer
+    }
+
+    #[test]
+    fn draws_the_canvas_into_the_grid() {
+        let (mut canvas, config) = walled((7, 4));
+        canvas.add_element(Element::Food, (1, 1), Some(RED), None);
+        canvas.add_creature(snake((4, 2), &[((2, 2), Element::BodyPartHori), ((3, 2), Element::BodyPartHori)]));
+
+        let renderer = render(&canvas, &config);
+        assert_eq!(renderer.lines(), ["#######", "#*    #", "# --@ #", "#######"]);
+        assert_eq!(renderer.cell((4, 2)), ('@', RED));
+        assert_eq!(renderer.cell((9, 9)), BLANK);
+        assert_eq!(renderer.frames, 1);
+    }
+
+    #[test]
+    fn only_presented_frames_are_shown() {
+        let (canvas, config) = walled((3, 3));
+        let mut renderer = render(&canvas, &config);
+        renderer.clear();
+        renderer.draw_text((0, 1), "abcd", RED);
+        assert_eq!(renderer.lines(), ["###", "# #", "###"]);
+        renderer.present().unwrap();
+        // Text is cut at the right edge
+        assert_eq!(renderer.lines(), ["   ", "abc", "   "]);
+    }
+
+    #[test]
+    fn diff_joins_changed_neighbours_on_a_row() {
+        const BLUE: Color = Color::Rgb { r: 0, g: 0, b: 255 };
+        let mut buffer = FrameBuffer::new((5, 2));
+        buffer.set((1, 0), ('a', RED));
+        buffer.set((2, 0), ('b', BLUE));
+        buffer.set((4, 0), ('c', RED));
+        buffer.set((0, 1), ('d', RED));
+        let runs: Vec<((u16, u16), Vec<Cell>)> = buffer.diff().into_iter().map(|run| (run.start, run.cells)).collect();
+        // The end of a row does not continue on the next one
+        assert_eq!(runs, [((1, 0), vec![('a', RED), ('b', BLUE)]), ((4, 0), vec![('c', RED)]), ((0, 1), vec![('d', RED)])]);
+
+        assert!(buffer.diff().is_empty());
+
+        buffer.clear();
+        buffer.set((1, 0), ('a', RED));
+        buffer.set((2, 0), ('b', RED));
+        let runs: Vec<((u16, u16), Vec<Cell>)> = buffer.diff().into_iter().map(|run| (run.start, run.cells)).collect();
+        assert_eq!(runs, [((2, 0), vec![('b', RED)]), ((4, 0), vec![BLANK]), ((0, 1), vec![BLANK])]);
+    }
+
+    #[test]
+    fn joints_follow_both_neighbours() {
+        let (up, down, left, right) = ((0, -1), (0, 1), (-1, 0), (1, 0));
+        assert_eq!(joint(up, down), Element::LineVert);
+        assert_eq!(joint(left, right), Element::LineHori);
+        assert_eq!(joint(up, right), Element::LineUpRight);
+        assert_eq!(joint(left, up), Element::LineUpLeft);
+        assert_eq!(joint(down, right), Element::LineDownRight);
+        assert_eq!(joint(left, down), Element::LineDownLeft);
+        assert_eq!(joint((1, -1), (-1, 1)), Element::LineLeftLean);
+        assert_eq!(joint((-1, -1), (1, 1)), Element::LineRightLean);
+        assert_eq!(joint((-1, -1), (1, -1)), Element::LineCross);
+        assert_eq!(joint(down, (1, -1)), Element::LineLeftLean);
+        // Across the edges of a wrapping world
+        assert_eq!(side((0, 5), (9, 5)), left);
+        assert_eq!(side((9, 5), (0, 5)), right);
+    }
+
+    #[test]
+    fn connected_bodies_turn_with_corners() {
+        let (mut canvas, mut config) = walled((6, 5));
+        config.body_style = BodyStyle::Connected;
+        // Down from the tail, then right to the head
+        canvas.add_creature(snake((3, 3), &[((2, 1), Element::BodyPartVert), ((2, 2), Element::BodyPartVert), ((2, 3), Element::BodyPartHori)]));
+        assert_eq!(render(&canvas, &config).lines(), ["######", "# |  #", "# |  #", "# +@ #", "######"]);
+
+        // Dying creatures lose their head first
+        canvas.remove_creature_part(0, 0);
+        assert_eq!(render(&canvas, &config).lines(), ["######", "# |  #", "# |  #", "# |  #", "######"]);
+    }
+}
@@ -0,0 +1,178 @@
+use crossterm::style::Color;
+use rand::{Rng, SeedableRng};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use crate::components::{CanvasParts, CanvasState, Element, SimRng};
+use crate::config::Config;
+use crate::map::MapCell;
+use crate::snapshot::Snapshot;
+use crate::terrain::TerrainWalls;
+use crate::species::SpeciesRegistry;
+use crate::{part_handler, pathfinder};
+
+/// A complete world: everything on the canvas, the bookkeeping of the run and the generator driving it.
+pub struct Simulation {
+    pub canvas: CanvasParts,
+    pub state: CanvasState,
+    pub config: Config,
+    /// Behavior and settings of every species that can spawn
+    pub species: SpeciesRegistry,
+    rng: SimRng,
+    /// Workers searching the paths of the creatures, none runs everything on the calling thread
+    pool: Option<ThreadPool>,
+}
+
+impl Simulation {
+    /// Creates a new world bordered by walls, as described by an already validated `config`.
+    pub fn new(mut config: Config) -> Simulation {
+        let mut rng = SimRng::seed_from_u64(config.seed);
+        let canvas = create_canvas(&config, &mut rng);
+        let species = SpeciesRegistry::from_config(&config).expect("Species are resolved when the config is validated");
+        for (_, behavior, _) in species.iter() {
+            config.visuals.extend(behavior.glyphs());
+        }
+        let pool = match config.max_threads {
+            0 => None,
+            threads => ThreadPoolBuilder::new().num_threads(threads as usize).build().ok(),
+        };
+        let snapshot = config.snapshot.take();
+        let mut simulation = Simulation { canvas, state: CanvasState::new(0),
+                                          config, species, rng, pool };
+        if let Some(snapshot) = snapshot {
+            simulation.resume(snapshot);
+        }
+        simulation
+    }
+
+    /// Captures the world and the generator, so the run can be continued later.
+    pub fn snapshot(&self) -> Snapshot {
+        let species = self.species.iter().map(|(_, behavior, _)| behavior.name().to_string()).collect();
+        Snapshot::new(self.config.seed, self.state.iterations, species, self.canvas.clone(), self.rng.clone())
+    }
+
+    /// Continues the run captured in `snapshot`, which has to be taken with the same species.
+    pub fn resume(&mut self, snapshot: Snapshot) {
+        self.config.seed = snapshot.seed;
+        self.config.size = snapshot.canvas.size;
+        self.config.wrap = snapshot.canvas.wrap;
+        self.canvas = snapshot.canvas;
+        self.rng = snapshot.rng;
+        self.state = CanvasState::new(snapshot.iterations);
+    }
+
+    /// Throws the current world away and builds a fresh one of `size`.
+    /// The generator keeps running, so a seeded session stays reproducible across restarts, and evolution continues from the same gene pool.
+    pub fn restart(&mut self, size: (u16, u16)) {
+        self.config.size = size;
+        let gene_pool = std::mem::take(&mut self.canvas.gene_pool);
+        self.canvas = create_canvas(&self.config, &mut self.rng);
+        self.canvas.gene_pool = gene_pool;
+        self.state = CanvasState::new(0);
+    }
+
+    /// Advances the world by one iteration.
+    pub fn step(&mut self) {
+        self.state.deaths.clear();
+        pathfinder::head_handle(&mut self.canvas, &self.species, self.pool.as_ref());
+        part_handler::spawner_handle(&mut self.canvas, &self.config, &self.species, &mut self.rng);
+        part_handler::breed_handle(&mut self.canvas, &self.config, &self.species, &mut self.rng);
+        part_handler::handle_killed(&mut self.canvas, &mut self.state, &self.species);
+        part_handler::spawn_food(&mut self.canvas, &self.config, &mut self.rng);
+        self.canvas.alive.iter_mut().for_each(|creature| creature.age += 1);
+        self.state.iterations += 1;
+    }
+
+    pub fn size(&self) -> (u16, u16) {
+        self.canvas.size
+    }
+}
+
+fn create_canvas(config: &Config, rng: &mut SimRng) -> CanvasParts{
+    let (size, spawners) = (config.size, config.spawners);
+    let mut canvas = CanvasParts::new(size, config.wrap); // Create an empty canvas to store the parts
+    let wall_color = Color::Rgb { r: 255, g: 60, b: 70 }; // Create a new color for the walls    
+
+    // A wrapped world has no edges to wall off
+    for y in (0..size.1).filter(|_| !config.wrap) {
+        if y == 0 || y == size.1 - 1 {
+            for x in 0..size.0 {
+                canvas.add_element(Element::Wall, (x, y), Some(wall_color), None);
+            }
+        } else {
+            canvas.add_element(Element::Wall, (size.0 - 1, y), Some(wall_color), None);
+            canvas.add_element(Element::Wall, (0, y), Some(wall_color), None);
+        }
+    }
+    if let Some(map) = &config.map {
+        for (position, cell) in map.place(size, config.scale_map) {
+            // The border stays where it is, and scaled walls may cover spawners and food
+            if canvas.grid().is_occupied(position) {
+                continue;
+            }
+            match cell {
+                MapCell::Wall => canvas.add_element(Element::Wall, position, Some(wall_color), None),
+                MapCell::Spawn => canvas.add_element(Element::Spawn, position, Some(Color::Rgb { r: 10, g: 255, b: 10 }), None),
+                MapCell::Food => {
+                    let color = part_handler::get_unused_color(&canvas.alive, rng);
+                    canvas.add_element(Element::Food, position, Some(color), None);
+                }
+                MapCell::Empty => (),
+            }
+        }
+    }
+
+    // Spawners given by the map replace the random ones
+    let map_spawners = canvas.interactable.iter().any(|part| part.element == Element::Spawn);
+    let spawner_ranges = (size.0-1) / spawners;
+    for i in (0..spawners).filter(|_| !map_spawners) {
+        let mut position = (rng.gen_range(((spawner_ranges*i)+1)..spawner_ranges*(i+1)), rng.gen_range(1..size.1-1));
+        // Walls of a map may cover the whole band, which then stays without a spawner
+        for _ in 0..100 {
+            if !canvas.grid().is_occupied(position) {
+                canvas.add_element(Element::Spawn, position, Some(Color::Rgb { r: 10, g: 255, b: 10 }), None);
+                break;
+            }
+            position = (rng.gen_range(((spawner_ranges*i)+1)..spawner_ranges*(i+1)), rng.gen_range(1..size.1-1));
+        }
+    }
+
+    if let Some(terrain) = config.terrain {
+        let mut walls = TerrainWalls::generate(terrain, size, config.wrap, rng);
+        let spawner_positions: Vec<(u16, u16)> = canvas.interactable.iter().filter(|part| part.element == Element::Spawn).map(|part| part.position).collect();
+        walls.connect(&spawner_positions);
+        for position in walls.positions() {
+            if !canvas.grid().is_occupied(position) {
+                canvas.add_element(Element::Wall, position, Some(wall_color), None);
+            }
+        }
+    }
+    
+    canvas // Return the canvas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The whole world and generator after `iterations` of a run with `seed`
+    fn run(seed: u64, max_threads: u32, iterations: usize) -> String {
+        let config = Config { seed, max_threads, ..Config::default() };
+        config.validate().unwrap();
+        let mut simulation = Simulation::new(config);
+        for _ in 0..iterations {
+            simulation.step();
+        }
+        serde_json::to_string(&simulation.snapshot()).unwrap()
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_run() {
+        assert_eq!(run(3, 0, 300), run(3, 0, 300));
+        assert_ne!(run(3, 0, 300), run(4, 0, 300));
+    }
+
+    #[test]
+    fn threads_do_not_change_the_run() {
+        assert_eq!(run(3, 0, 300), run(3, 4, 300));
+    }
+}
@@ -0,0 +1,87 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::components::{CanvasParts, SimRng};
+
+/// Layout of the saved world, snapshots of any other version are refused
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// Everything needed to continue a run exactly where it was saved, stored as JSON.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub seed: u64,
+    pub iterations: u128,
+    /// Names of the species in registry order, the species of the creatures refer to them by position
+    pub species: Vec<String>,
+    pub canvas: CanvasParts,
+    pub rng: SimRng,
+}
+
+impl Snapshot {
+    pub fn new(seed: u64, iterations: u128, species: Vec<String>, canvas: CanvasParts, rng: SimRng) -> Snapshot {
+        Snapshot { version: SNAPSHOT_VERSION, seed, iterations, species, canvas, rng }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let content = serde_json::to_string(self).map_err(|err| format!("Cannot serialize the snapshot: {}", err))?;
+        fs::write(path, content).map_err(|err| format!("Cannot write {}: {}", path.display(), err))
+    }
+
+    /// Reads a snapshot, its grid is rebuilt from the parts.
+    pub fn load(path: &Path) -> Result<Snapshot, String> {
+        let content = fs::read_to_string(path).map_err(|err| format!("Cannot read {}: {}", path.display(), err))?;
+        let mut snapshot: Snapshot = serde_json::from_str(&content).map_err(|err| format!("Invalid snapshot {}: {}", path.display(), err))?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(format!("The snapshot {} has version {}, only version {} can be read", path.display(), snapshot.version, SNAPSHOT_VERSION));
+        }
+        snapshot.canvas.rebuild_grid();
+        Ok(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Simulation};
+
+    fn run(config: Config, iterations: usize) -> Simulation {
+        let mut simulation = Simulation::new(config);
+        for _ in 0..iterations {
+            simulation.step();
+        }
+        simulation
+    }
+
+    fn seeded() -> Config {
+        let config = Config { seed: 7, ..Config::default() };
+        config.validate().unwrap();
+        config
+    }
+
+    #[test]
+    fn a_loaded_run_continues_like_an_uninterrupted_one() {
+        let path = std::env::temp_dir().join(format!("dance-of-lines-round-trip-{}.json", std::process::id()));
+        run(seeded(), 150).snapshot().save(&path).unwrap();
+        let snapshot = Snapshot::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        let resumed = run(Config { snapshot: Some(snapshot.unwrap()), ..seeded() }, 150);
+        let straight = run(seeded(), 300);
+        assert_eq!(resumed.state.iterations, 300);
+        assert_eq!(serde_json::to_string(&resumed.snapshot()).unwrap(), serde_json::to_string(&straight.snapshot()).unwrap());
+    }
+
+    #[test]
+    fn other_versions_are_refused() {
+        let path = std::env::temp_dir().join(format!("dance-of-lines-version-{}.json", std::process::id()));
+        let mut snapshot = run(seeded(), 0).snapshot();
+        snapshot.version = SNAPSHOT_VERSION + 1;
+        snapshot.save(&path).unwrap();
+        let loaded = Snapshot::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(loaded.err().unwrap().contains(&format!("has version {}", SNAPSHOT_VERSION + 1)));
+    }
+}
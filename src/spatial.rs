@@ -0,0 +1,138 @@
+use crate::components::Element;
+
+/// Elements on every cell of the canvas, so looking up a position does not need to scan every part.
+/// Kept in sync by the methods of `CanvasParts`, parts changed directly are not tracked.
+#[derive(Clone, Default)]
+pub struct Grid {
+    width: usize,
+    height: usize,
+    /// Distances are measured around the edges
+    wrap: bool,
+    cells: Vec<Vec<Element>>,
+}
+
+impl Grid {
+    pub fn new(size: (u16, u16), wrap: bool) -> Grid {
+        // Creatures may step onto the column and row right after the canvas before they are killed
+        let (width, height) = (size.0 as usize + 1, size.1 as usize + 1);
+        Grid { width, height, wrap, cells: vec![Vec::new(); width * height] }
+    }
+
+    /// Distance between two positions on one axis of length `length`, around the edge if that is shorter in a wrapped world.
+    fn axis_distance(&self, from: u16, to: u16, length: usize) -> i64 {
+        let distance = (from as i64 - to as i64).abs();
+        if self.wrap {
+            distance.min(length as i64 - distance)
+        } else {
+            distance
+        }
+    }
+
+    fn index(&self, position: (u16, u16)) -> Option<usize> {
+        let (x, y) = (position.0 as usize, position.1 as usize);
+        (x < self.width && y < self.height).then_some(y * self.width + x)
+    }
+
+    pub fn insert(&mut self, position: (u16, u16), element: Element) {
+        if let Some(index) = self.index(position) {
+            self.cells[index].push(element);
+        }
+    }
+
+    /// Removes one `element` from `position`, others of the same kind on the cell stay.
+    pub fn remove(&mut self, position: (u16, u16), element: Element) {
+        if let Some(index) = self.index(position) {
+            if let Some(found) = self.cells[index].iter().position(|elem| *elem == element) {
+                self.cells[index].remove(found);
+            }
+        }
+    }
+
+    /// Elements on `position` in the order they were placed, positions outside the canvas are empty.
+    pub fn at(&self, position: (u16, u16)) -> &[Element] {
+        match self.index(position) {
+            Some(index) => &self.cells[index],
+            None => &[],
+        }
+    }
+
+    pub fn is_occupied(&self, position: (u16, u16)) -> bool {
+        !self.at(position).is_empty()
+    }
+}
+
+/// The part of a grid a creature can see, everything further than `radius` from `center` appears empty.
+pub struct Sight<'a> {
+    grid: &'a Grid,
+    center: (u16, u16),
+    radius: i32,
+}
+
+impl<'a> Sight<'a> {
+    pub fn new(grid: &'a Grid, center: (u16, u16), radius: i32) -> Sight<'a> {
+        Sight { grid, center, radius }
+    }
+
+    pub fn at(&self, position: (u16, u16)) -> &'a [Element] {
+        // The grid is one column and row larger than the world
+        let distance = self.grid.axis_distance(position.0, self.center.0, self.grid.width - 1).pow(2)
+                     + self.grid.axis_distance(position.1, self.center.1, self.grid.height - 1).pow(2);
+        if distance <= (self.radius as i64).pow(2) {
+            self.grid.at(position)
+        } else {
+            &[]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Simulation};
+
+    /// Whether both grids hold the same elements on every position, in any order
+    fn same_elements(grid: &Grid, other: &Grid) -> bool {
+        (0..grid.cells.len()).all(|index| {
+            let (cell, other) = (&grid.cells[index], &other.cells[index]);
+            cell.len() == other.len() && cell.iter().all(|element| cell.iter().filter(|e| *e == element).count() == other.iter().filter(|e| *e == element).count())
+        })
+    }
+
+    #[test]
+    fn the_grid_follows_every_change_of_the_parts() {
+        for wrap in [false, true] {
+            let config = Config { seed: 2, wrap, size: (40, 14), spawn_chance: 1.0, ..Config::default() };
+            config.validate().unwrap();
+            let mut simulation = Simulation::new(config);
+            let (mut deaths, mut meals, mut trimmed) = (0, 0, 0);
+            for _ in 0..250 {
+                let food = simulation.canvas.interactable.len();
+                let lengths: Vec<(u32, usize)> = simulation.canvas.alive.iter().map(|creature| (creature.lineage, creature.parts.len())).collect();
+                simulation.step();
+                deaths += simulation.state.deaths.len();
+                meals += food.saturating_sub(simulation.canvas.interactable.len());
+                trimmed += simulation.canvas.alive.iter().filter(|creature| lengths.iter().any(|(lineage, length)| *lineage == creature.lineage && creature.parts.len() < *length)).count();
+
+                let mut rebuilt = simulation.canvas.clone();
+                rebuilt.rebuild_grid();
+                assert!(same_elements(simulation.canvas.grid(), rebuilt.grid()), "grid out of sync after iteration {}", simulation.state.iterations);
+            }
+            assert!(deaths > 0 && meals > 0 && trimmed > 0, "{} deaths, {} meals and {} trimmed", deaths, meals, trimmed);
+        }
+    }
+
+    #[test]
+    fn sight_reaches_around_the_edges_of_a_wrapped_world() {
+        let mut grid = Grid::new((20, 10), true);
+        grid.insert((19, 5), Element::Food);
+        grid.insert((0, 9), Element::Wall);
+        let sight = Sight::new(&grid, (1, 5), 2);
+        assert_eq!(sight.at((19, 5)), [Element::Food]);
+        assert!(sight.at((0, 9)).is_empty());
+        assert_eq!(Sight::new(&grid, (0, 0), 1).at((0, 9)), [Element::Wall]);
+
+        let mut walled = Grid::new((20, 10), false);
+        walled.insert((19, 5), Element::Food);
+        assert!(Sight::new(&walled, (1, 5), 2).at((19, 5)).is_empty());
+    }
+}
@@ -0,0 +1,401 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::components::{CanvasParts, DirectionX, DirectionY, Element};
+use crate::config::{Config, SpeciesSettings};
+use crate::spatial::Sight;
+
+/// Identifies a species registered in a `SpeciesRegistry`.
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Species(pub usize);
+
+/// Everything that makes a species behave the way it does.
+/// Its tunable numbers are kept apart in `SpeciesSettings`, so they can be set from the config.
+pub trait SpeciesBehavior: Send + Sync {
+    /// Name used in the config file and in summaries
+    fn name(&self) -> &str;
+
+    fn default_settings(&self) -> SpeciesSettings;
+
+    fn head(&self) -> Element;
+
+    /// Body part left behind on the previous head position after a move in `moved_in_direction`
+    fn body_part(&self, moved_in_direction: (DirectionX, DirectionY)) -> Element;
+
+    /// Attraction of a position holding `element`, the pathfinder follows the highest sum
+    fn path_value(&self, element: Element) -> i64;
+
+    /// Whether moving onto `position` kills the creature, and the indices of creatures it kills on the way
+    fn colision(&self, sight: &Sight, position: &(u16, u16), canvas: &CanvasParts) -> (bool, Option<Vec<usize>>);
+
+    /// Predators move after all other creatures, so they aim at where their prey is now
+    fn is_predator(&self) -> bool {
+        false
+    }
+
+    /// Glyphs of elements that have none in the default visuals
+    fn glyphs(&self) -> Vec<(Element, char)> {
+        Vec::new()
+    }
+}
+
+/// The lines themselves. Detached snakes turn sharper and lose their tail after a while.
+pub struct Snake {
+    pub detached: bool,
+}
+
+impl SpeciesBehavior for Snake {
+    fn name(&self) -> &str {
+        if self.detached { "detached_snake" } else { "snake" }
+    }
+
+    fn default_settings(&self) -> SpeciesSettings {
+        if self.detached {
+            SpeciesSettings { fov: 2, speed: 1, sight_radius: 4, max_length: 20, spawn_weight: 1,
+                              max_energy: 120, food_energy: 60, meal_growth: 6, breed_length: 16 }
+        } else {
+            SpeciesSettings { fov: 1, speed: 1, sight_radius: 4, max_length: 0, spawn_weight: 8,
+                              max_energy: 200, food_energy: 80, meal_growth: 10, breed_length: 60 }
+        }
+    }
+
+    fn head(&self) -> Element {
+        Element::BodyPartHead
+    }
+
+    fn body_part(&self, moved_in_direction: (DirectionX, DirectionY)) -> Element {
+        match moved_in_direction {
+            (DirectionX::None, DirectionY::Up) =>  Element::BodyPartVert,
+            (DirectionX::Right,DirectionY::Up) =>  Element::BodyPartLeftLean,
+            (DirectionX::Left, DirectionY::Up) =>  Element::BodyPartRightLean,
+            (DirectionX::None, DirectionY::Down) =>Element::BodyPartVert,
+            (DirectionX::Right,DirectionY::Down) =>Element::BodyPartRightLean,
+            (DirectionX::Left, DirectionY::Down) =>Element::BodyPartLeftLean,
+            (DirectionX::Right,DirectionY::None) =>Element::BodyPartHori,
+            (DirectionX::Left, DirectionY::None) =>Element::BodyPartHori,
+            _ => Element::BodyPartHori,
+        }
+    }
+
+    fn path_value(&self, element: Element) -> i64 {
+        match element {
+            Element::Wall | Element::Spawn => -100,
+            Element::BodyPartHori | Element::BodyPartVert | Element::WespBody | Element::WespHead |
+            Element::BodyPartLeftLean | Element::BodyPartRightLean | Element::BodyPartHead => -100,
+            Element::Head(_) | Element::Body(_) => -100,
+            Element::LineVert | Element::LineHori | Element::LineLeftLean | Element::LineRightLean | Element::LineUpRight |
+            Element::LineUpLeft | Element::LineDownRight | Element::LineDownLeft | Element::LineCross => -100,
+            Element::Food => 10,
+        }
+    }
+
+    fn colision(&self, sight: &Sight, position: &(u16, u16), _: &CanvasParts) -> (bool, Option<Vec<usize>>) {
+        let colision = sight.at(*position).iter().any(|elem| *elem != Element::Food);
+        (colision, None)
+    }
+}
+
+/// Hunts the heads of snakes and dies on food.
+pub struct Wesp;
+
+impl SpeciesBehavior for Wesp {
+    fn name(&self) -> &str {
+        "wesp"
+    }
+
+    fn default_settings(&self) -> SpeciesSettings {
+        SpeciesSettings { fov: 1, speed: 2, sight_radius: 4, max_length: 8, spawn_weight: 1,
+                          max_energy: 150, food_energy: 150, meal_growth: 2, breed_length: 8 }
+    }
+
+    fn head(&self) -> Element {
+        Element::WespHead
+    }
+
+    fn body_part(&self, _: (DirectionX, DirectionY)) -> Element {
+        Element::WespBody
+    }
+
+    fn path_value(&self, element: Element) -> i64 {
+        match element {
+            Element::Wall | Element::Spawn | Element::Food => -100,
+            Element::WespBody => -20,
+            Element::BodyPartHead => 30,
+            _ => 1,
+        }
+    }
+
+    fn colision(&self, sight: &Sight, position: &(u16, u16), canvas: &CanvasParts) -> (bool, Option<Vec<usize>>) {
+        let mut wesp_kills: Vec<usize> = Vec::new();
+        let elements = sight.at(*position);
+        if elements.contains(&Element::BodyPartHead) {
+            wesp_kills.push(canvas.alive.iter().position(|creature| creature.parts.first().is_some_and(|head| head.position == *position)).unwrap());
+        }
+        let colision = elements.contains(&Element::Food);
+        (colision, Some(wesp_kills))
+    }
+
+    fn is_predator(&self) -> bool {
+        true
+    }
+}
+
+/// A species as written in a species file, keyed by its name there.
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SpeciesDefinition {
+    #[serde(skip)]
+    pub name: String,
+    pub head: char,
+    /// Leaves the line glyphs of snakes behind if not set
+    pub body: Option<char>,
+    pub fov: Option<isize>,
+    pub speed: Option<u8>,
+    pub sight_radius: Option<i32>,
+    pub max_length: Option<usize>,
+    pub spawn_weight: Option<u32>,
+    pub max_energy: Option<u32>,
+    pub food_energy: Option<u32>,
+    pub meal_growth: Option<usize>,
+    pub breed_length: Option<usize>,
+    /// Attraction of positions by element name, see `element_by_name`
+    #[serde(default)]
+    pub scores: HashMap<String, i64>,
+    /// Attraction of elements missing in `scores`
+    #[serde(default = "default_score")]
+    pub default_score: i64,
+    /// Elements that can be moved onto without a colision, food is eaten on the way
+    #[serde(default = "default_eats")]
+    pub eats: Vec<String>,
+    /// Species whose heads are killed by moving onto them
+    #[serde(default)]
+    pub kills: Vec<String>,
+}
+
+fn default_score() -> i64 {
+    -100
+}
+
+fn default_eats() -> Vec<String> {
+    vec!["food".to_string()]
+}
+
+impl SpeciesDefinition {
+    /// Settings given in the file, anything missing is taken from `DataSpecies::default_settings`
+    pub fn settings(&self) -> SpeciesSettings {
+        let defaults = DATA_SPECIES_SETTINGS;
+        SpeciesSettings {
+            fov: self.fov.unwrap_or(defaults.fov),
+            speed: self.speed.unwrap_or(defaults.speed),
+            sight_radius: self.sight_radius.unwrap_or(defaults.sight_radius),
+            max_length: self.max_length.unwrap_or(defaults.max_length),
+            spawn_weight: self.spawn_weight.unwrap_or(defaults.spawn_weight),
+            max_energy: self.max_energy.unwrap_or(defaults.max_energy),
+            food_energy: self.food_energy.unwrap_or(defaults.food_energy),
+            meal_growth: self.meal_growth.unwrap_or(defaults.meal_growth),
+            breed_length: self.breed_length.unwrap_or(defaults.breed_length),
+        }
+    }
+}
+
+const DATA_SPECIES_SETTINGS: SpeciesSettings = SpeciesSettings { fov: 1, speed: 1, sight_radius: 4, max_length: 0, spawn_weight: 1,
+                                                                 max_energy: 200, food_energy: 80, meal_growth: 10, breed_length: 60 };
+
+/// Elements that exist without any species file, by the name used in species files
+const NAMED_ELEMENTS: [(&str, Element); 10] = [
+    ("wall", Element::Wall),
+    ("spawn", Element::Spawn),
+    ("food", Element::Food),
+    ("body_part_vert", Element::BodyPartVert),
+    ("body_part_hori", Element::BodyPartHori),
+    ("body_part_left_lean", Element::BodyPartLeftLean),
+    ("body_part_right_lean", Element::BodyPartRightLean),
+    ("body_part_head", Element::BodyPartHead),
+    ("wesp_head", Element::WespHead),
+    ("wesp_body", Element::WespBody),
+];
+
+/// Elements only drawn by the connected body style, named for theme files
+const LINE_ELEMENTS: [(&str, Element); 9] = [
+    ("line_vert", Element::LineVert),
+    ("line_hori", Element::LineHori),
+    ("line_left_lean", Element::LineLeftLean),
+    ("line_right_lean", Element::LineRightLean),
+    ("line_up_right", Element::LineUpRight),
+    ("line_up_left", Element::LineUpLeft),
+    ("line_down_right", Element::LineDownRight),
+    ("line_down_left", Element::LineDownLeft),
+    ("line_cross", Element::LineCross),
+];
+
+/// Every element a glyph theme covers, by its name in theme files
+pub fn themed_elements() -> impl Iterator<Item = (&'static str, Element)> {
+    NAMED_ELEMENTS.iter().chain(LINE_ELEMENTS.iter()).copied()
+}
+
+/// Looks up an element by the name used in species files.
+/// Parts of species from files are named `<species>_head` and `<species>_body`, `names` lists all species in registry order.
+pub fn element_by_name(name: &str, names: &[String]) -> Option<Element> {
+    if let Some((_, element)) = NAMED_ELEMENTS.iter().find(|(named, _)| *named == name) {
+        return Some(*element);
+    }
+    let index = |species: &str| names.iter().position(|name| name == species).map(Species);
+    name.strip_suffix("_head").and_then(index).map(Element::Head)
+        .or_else(|| name.strip_suffix("_body").and_then(index).map(Element::Body))
+}
+
+/// A species defined in a species file instead of code.
+pub struct DataSpecies {
+    name: String,
+    species: Species,
+    head: char,
+    body: Option<char>,
+    scores: HashMap<Element, i64>,
+    default_score: i64,
+    eats: HashSet<Element>,
+    kills: HashSet<Species>,
+}
+
+impl DataSpecies {
+    /// Resolves the names used in `definition`, which is registered as `species` among `names`.
+    pub fn new(definition: &SpeciesDefinition, species: Species, names: &[String]) -> Result<DataSpecies, String> {
+        let element = |name: &String| element_by_name(name, names)
+            .ok_or_else(|| format!("Unknown element '{}' in species {}", name, definition.name));
+        let scores = definition.scores.iter()
+            .map(|(name, score)| element(name).map(|element| (element, *score)))
+            .collect::<Result<HashMap<Element, i64>, String>>()?;
+        let eats = definition.eats.iter().map(element).collect::<Result<HashSet<Element>, String>>()?;
+        let kills = definition.kills.iter()
+            .map(|name| names.iter().position(|species| species == name).map(Species)
+                .ok_or_else(|| format!("Unknown species '{}' killed by {}", name, definition.name)))
+            .collect::<Result<HashSet<Species>, String>>()?;
+        Ok(DataSpecies { name: definition.name.clone(), species, head: definition.head, body: definition.body,
+                         scores, default_score: definition.default_score, eats, kills })
+    }
+}
+
+impl SpeciesBehavior for DataSpecies {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn default_settings(&self) -> SpeciesSettings {
+        DATA_SPECIES_SETTINGS
+    }
+
+    fn head(&self) -> Element {
+        Element::Head(self.species)
+    }
+
+    fn body_part(&self, moved_in_direction: (DirectionX, DirectionY)) -> Element {
+        match self.body {
+            Some(_) => Element::Body(self.species),
+            None => Snake { detached: false }.body_part(moved_in_direction),
+        }
+    }
+
+    fn path_value(&self, element: Element) -> i64 {
+        self.scores.get(&element).copied().unwrap_or(self.default_score)
+    }
+
+    fn colision(&self, sight: &Sight, position: &(u16, u16), canvas: &CanvasParts) -> (bool, Option<Vec<usize>>) {
+        let mut kills: Vec<usize> = Vec::new();
+        let mut colision = false;
+        for element in sight.at(*position) {
+            if self.eats.contains(element) {
+                continue;
+            }
+            let prey = canvas.alive.iter().position(|creature| creature.parts.first().is_some_and(|head| head.position == *position && head.element == *element));
+            match prey {
+                Some(index) if self.kills.contains(&canvas.alive[index].species) => kills.push(index),
+                _ => colision = true,
+            }
+        }
+        (colision, Some(kills))
+    }
+
+    fn is_predator(&self) -> bool {
+        !self.kills.is_empty()
+    }
+
+    fn glyphs(&self) -> Vec<(Element, char)> {
+        let mut glyphs = vec![(Element::Head(self.species), self.head)];
+        if let Some(body) = self.body {
+            glyphs.push((Element::Body(self.species), body));
+        }
+        glyphs
+    }
+}
+
+/// The species shipped with the simulation, new ones only need to be added here.
+pub fn builtin_species() -> Vec<Box<dyn SpeciesBehavior>> {
+    vec![Box::new(Snake { detached: false }), Box::new(Snake { detached: true }), Box::new(Wesp)]
+}
+
+/// All species a simulation knows, each with the settings it runs with.
+pub struct SpeciesRegistry {
+    entries: Vec<(Box<dyn SpeciesBehavior>, SpeciesSettings)>,
+}
+
+impl SpeciesRegistry {
+    /// Registers the built-in species followed by the ones from species files, with their settings from `config`.
+    pub fn from_config(config: &Config) -> Result<SpeciesRegistry, String> {
+        let builtin = builtin_species();
+        let names: Vec<String> = builtin.iter().map(|behavior| behavior.name().to_string())
+            .chain(config.species_definitions.iter().map(|definition| definition.name.clone()))
+            .collect();
+        let mut behaviors = builtin;
+        for definition in config.species_definitions.iter() {
+            // Species are registered in the order of `names`, so the next id is known up front
+            let species = Species(behaviors.len());
+            behaviors.push(Box::new(DataSpecies::new(definition, species, &names)?));
+        }
+
+        let mut registry = SpeciesRegistry { entries: Vec::new() };
+        for behavior in behaviors {
+            let settings = config.species.get(behavior.name()).copied().unwrap_or_else(|| behavior.default_settings());
+            registry.register(behavior, settings);
+        }
+        Ok(registry)
+    }
+
+    pub fn register(&mut self, behavior: Box<dyn SpeciesBehavior>, settings: SpeciesSettings) -> Species {
+        self.entries.push((behavior, settings));
+        Species(self.entries.len() - 1)
+    }
+
+    pub fn behavior(&self, species: Species) -> &dyn SpeciesBehavior {
+        self.entries[species.0].0.as_ref()
+    }
+
+    pub fn settings(&self, species: Species) -> &SpeciesSettings {
+        &self.entries[species.0].1
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<Species> {
+        self.entries.iter().position(|(behavior, _)| behavior.name() == name).map(Species)
+    }
+
+    /// Every element a creature can come across, the parts of species from files included
+    pub fn elements(&self) -> Vec<Element> {
+        let mut elements: Vec<Element> = NAMED_ELEMENTS.iter().map(|(_, element)| *element).collect();
+        for (_, behavior, _) in self.iter() {
+            elements.extend(behavior.glyphs().into_iter().map(|(element, _)| element));
+        }
+        elements
+    }
+
+    /// Name of `element` as used in species files
+    pub fn element_name(&self, element: Element) -> String {
+        match element {
+            Element::Head(species) => format!("{}_head", self.behavior(species).name()),
+            Element::Body(species) => format!("{}_body", self.behavior(species).name()),
+            _ => themed_elements().find(|(_, named)| *named == element).map(|(name, _)| name.to_string()).unwrap_or_default(),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Species, &dyn SpeciesBehavior, &SpeciesSettings)> {
+        self.entries.iter().enumerate().map(|(index, (behavior, settings))| (Species(index), behavior.as_ref(), settings))
+    }
+}
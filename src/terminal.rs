@@ -0,0 +1,137 @@
+use std::io::{self, Write};
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crossterm::{cursor, execute, queue, style::{Color, Print, ResetColor, SetForegroundColor}, terminal::{self, disable_raw_mode, enable_raw_mode, BeginSynchronizedUpdate, Clear, ClearType, DisableLineWrap, EnableLineWrap, EndSynchronizedUpdate, EnterAlternateScreen, LeaveAlternateScreen}};
+use dance_of_lines::render::{Cell, FrameBuffer, Renderer};
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+
+/// Whether the alternate screen was entered, read by the panic hook which has no access to the guard
+static ALTERNATE_SCREEN: AtomicBool = AtomicBool::new(false);
+/// Set once the terminal was given back, on a panic both the hook and the dropped guard try to
+static RESTORED: AtomicBool = AtomicBool::new(false);
+
+/// Keeps the terminal prepared for drawing and gives it back to the shell when dropped,
+/// on panics as well as on termination signals.
+pub struct TerminalGuard {
+    /// Set once a termination signal arrived, the draw loop is expected to return as soon as possible
+    pub stop: Arc<AtomicBool>,
+}
+
+impl TerminalGuard {
+    /// Prepares the terminal, on the alternate screen the previous content of the terminal is restored on exit.
+    pub fn enter(alternate_screen: bool) -> io::Result<TerminalGuard> {
+        // The hook runs before unwinding reaches the guard, restoring here keeps the panic message readable
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            restore();
+            default_hook(info);
+        }));
+
+        let stop = Arc::new(AtomicBool::new(false));
+        for signal in [SIGTERM, SIGHUP, SIGINT] {
+            signal_hook::flag::register(signal, Arc::clone(&stop))?;
+        }
+
+        RESTORED.store(false, Ordering::Relaxed);
+        enable_raw_mode()?;
+        if alternate_screen {
+            execute!(io::stdout(), EnterAlternateScreen)?;
+        }
+        ALTERNATE_SCREEN.store(alternate_screen, Ordering::Relaxed);
+        // Writing the bottom right cell would scroll the whole canvas with line wrap enabled
+        execute!(io::stdout(), cursor::Hide, DisableLineWrap)?;
+        Ok(TerminalGuard { stop })
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+/// Puts the terminal back into the state the shell expects, errors are ignored as there is nothing left to do about them.
+fn restore() {
+    if RESTORED.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    let mut stdout = io::stdout();
+    let _ = execute!(stdout, crossterm::style::ResetColor, cursor::Show, EnableLineWrap);
+    if ALTERNATE_SCREEN.load(Ordering::Relaxed) {
+        let _ = execute!(stdout, LeaveAlternateScreen);
+    } else if let Ok((_, rows)) = terminal::size() {
+        // The last frame stays on screen, the prompt continues below it
+        let _ = execute!(stdout, cursor::MoveTo(0, rows.saturating_sub(1)));
+        let _ = stdout.write_all(b"\r\n");
+    }
+    let _ = disable_raw_mode();
+    let _ = stdout.flush();
+}
+
+/// Draws to the terminal, writing only the cells that changed since the last frame.
+pub struct CrosstermRenderer {
+    buffer: FrameBuffer,
+    /// The next frame starts from an empty screen
+    clear_screen: bool,
+}
+
+impl CrosstermRenderer {
+    /// A renderer for a screen of `size`, which is cleared with the first frame.
+    pub fn new(size: (u16, u16)) -> CrosstermRenderer {
+        CrosstermRenderer { buffer: FrameBuffer::new(size), clear_screen: true }
+    }
+
+    /// Starts over on an empty screen of `size`, like after the terminal was resized.
+    pub fn reset(&mut self, size: (u16, u16)) {
+        self.buffer.reset(size);
+        self.clear_screen = true;
+    }
+}
+
+impl Renderer for CrosstermRenderer {
+    fn size(&self) -> (u16, u16) {
+        self.buffer.size()
+    }
+
+    fn draw_cell(&mut self, position: (u16, u16), cell: Cell) {
+        self.buffer.set(position, cell);
+    }
+
+    fn clear_cell(&mut self, position: (u16, u16)) {
+        self.buffer.set(position, (' ', Color::Reset));
+    }
+
+    fn clear(&mut self) {
+        self.buffer.clear();
+    }
+
+    /// Writes the frame with a single write. Terminals supporting synchronized updates show it at once
+    /// instead of while it arrives, others ignore the sequence around it.
+    fn present(&mut self) -> io::Result<()> {
+        let mut frame: Vec<u8> = Vec::new();
+        queue!(frame, BeginSynchronizedUpdate)?;
+        if self.clear_screen {
+            queue!(frame, Clear(ClearType::All))?;
+            self.clear_screen = false;
+        }
+        let mut color = None;
+        for run in self.buffer.diff() {
+            queue!(frame, cursor::MoveTo(run.start.0, run.start.1))?;
+            for (glyph, cell_color) in run.cells {
+                // Neighbours mostly share the color of their creature or the walls
+                if color != Some(cell_color) {
+                    queue!(frame, SetForegroundColor(cell_color))?;
+                    color = Some(cell_color);
+                }
+                queue!(frame, Print(glyph))?;
+            }
+        }
+        queue!(frame, ResetColor, EndSynchronizedUpdate)?;
+
+        let mut stdout = io::stdout();
+        stdout.write_all(&frame)?;
+        stdout.flush()
+    }
+}
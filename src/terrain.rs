@@ -0,0 +1,302 @@
+use std::collections::{HashMap, VecDeque};
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::components::SimRng;
+
+/// Obstacles generated inside the world on every (re)start.
+#[derive(Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Terrain {
+    /// Scattered blobs of wall
+    Islands,
+    /// Rooms grown by a cellular automaton
+    Caves,
+    /// Corridors three cells wide
+    Maze,
+}
+
+/// Distance between the walls of the maze, leaving corridors one less wide
+const MAZE_PITCH: usize = 4;
+
+/// Which positions of the world are walled by the terrain.
+pub struct TerrainWalls {
+    width: usize,
+    height: usize,
+    /// Without border walls the terrain may cover the edges and wraps around them
+    wrap: bool,
+    blocked: Vec<bool>,
+}
+
+impl TerrainWalls {
+    /// Generates `terrain` for a world of `size`, staying off the border walls unless the world wraps.
+    pub fn generate(terrain: Terrain, size: (u16, u16), wrap: bool, rand_gen: &mut SimRng) -> TerrainWalls {
+        let mut walls = TerrainWalls { width: size.0 as usize, height: size.1 as usize, wrap, blocked: vec![false; size.0 as usize * size.1 as usize] };
+        match terrain {
+            Terrain::Islands => walls.islands(rand_gen),
+            Terrain::Caves => walls.caves(rand_gen),
+            Terrain::Maze => walls.maze(rand_gen),
+        }
+        if !wrap {
+            // The border is walled already
+            for y in 0..walls.height {
+                for x in 0..walls.width {
+                    if x == 0 || y == 0 || x == walls.width - 1 || y == walls.height - 1 {
+                        walls.set(x as i64, y as i64, false);
+                    }
+                }
+            }
+        }
+        walls
+    }
+
+    fn index(&self, x: i64, y: i64) -> Option<usize> {
+        let (x, y) = if self.wrap {
+            (x.rem_euclid(self.width as i64), y.rem_euclid(self.height as i64))
+        } else {
+            (x, y)
+        };
+        ((0..self.width as i64).contains(&x) && (0..self.height as i64).contains(&y)).then(|| y as usize * self.width + x as usize)
+    }
+
+    /// Positions outside a world with edges count as walls
+    fn is_blocked(&self, x: i64, y: i64) -> bool {
+        self.index(x, y).is_none_or(|index| self.blocked[index])
+    }
+
+    fn set(&mut self, x: i64, y: i64, blocked: bool) {
+        if let Some(index) = self.index(x, y) {
+            self.blocked[index] = blocked;
+        }
+    }
+
+    fn islands(&mut self, rand_gen: &mut SimRng) {
+        let count = self.width * self.height / 150;
+        for _ in 0..count {
+            let center = (rand_gen.gen_range(0..self.width) as i64, rand_gen.gen_range(0..self.height) as i64);
+            let radius: i64 = rand_gen.gen_range(1..=3);
+            for y in -radius..=radius {
+                for x in -radius..=radius {
+                    // Frayed edges look less like stamped circles
+                    if x * x + y * y <= radius * radius && (x * x + y * y < radius * radius || rand_gen.gen_bool(0.5)) {
+                        self.set(center.0 + x, center.1 + y, true);
+                    }
+                }
+            }
+        }
+    }
+
+    fn caves(&mut self, rand_gen: &mut SimRng) {
+        self.blocked.iter_mut().for_each(|blocked| *blocked = rand_gen.gen_bool(0.45));
+        for _ in 0..4 {
+            let mut next = self.blocked.clone();
+            for y in 0..self.height as i64 {
+                for x in 0..self.width as i64 {
+                    let neighbours = (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                        .filter(|&(dx, dy)| (dx, dy) != (0, 0) && self.is_blocked(x + dx, y + dy))
+                        .count();
+                    next[y as usize * self.width + x as usize] = neighbours >= 5 || (neighbours == 4 && self.is_blocked(x, y));
+                }
+            }
+            self.blocked = next;
+        }
+    }
+
+    fn maze(&mut self, rand_gen: &mut SimRng) {
+        let (cells_x, cells_y) = ((self.width - 1) / MAZE_PITCH, (self.height - 1) / MAZE_PITCH);
+        if cells_x == 0 || cells_y == 0 {
+            return;
+        }
+        // Walls on every line of the lattice, corridors are opened between visited cells
+        for y in 0..=cells_y * MAZE_PITCH {
+            for x in 0..=cells_x * MAZE_PITCH {
+                if x % MAZE_PITCH == 0 || y % MAZE_PITCH == 0 {
+                    self.set(x as i64, y as i64, true);
+                }
+            }
+        }
+
+        let mut visited = vec![false; cells_x * cells_y];
+        let mut stack = vec![(rand_gen.gen_range(0..cells_x), rand_gen.gen_range(0..cells_y))];
+        visited[stack[0].1 * cells_x + stack[0].0] = true;
+        while let Some(&(cx, cy)) = stack.last() {
+            let mut neighbours: Vec<(usize, usize)> = [(0, -1), (1, 0), (0, 1), (-1, 0)].iter()
+                .map(|(dx, dy)| (cx as i64 + dx, cy as i64 + dy))
+                .filter(|(nx, ny)| (0..cells_x as i64).contains(nx) && (0..cells_y as i64).contains(ny))
+                .map(|(nx, ny)| (nx as usize, ny as usize))
+                .filter(|(nx, ny)| !visited[ny * cells_x + nx])
+                .collect();
+            if neighbours.is_empty() {
+                stack.pop();
+                continue;
+            }
+            neighbours.shuffle(rand_gen);
+            let (nx, ny) = neighbours[0];
+            self.open_between((cx, cy), (nx, ny));
+            visited[ny * cells_x + nx] = true;
+            stack.push((nx, ny));
+        }
+    }
+
+    /// Removes the wall between two neighbouring maze cells.
+    fn open_between(&mut self, from: (usize, usize), to: (usize, usize)) {
+        let (low, high) = (from.min(to), from.max(to));
+        for step in 1..MAZE_PITCH {
+            if low.0 != high.0 {
+                self.set((high.0 * MAZE_PITCH) as i64, (low.1 * MAZE_PITCH + step) as i64, false);
+            } else {
+                self.set((low.0 * MAZE_PITCH + step) as i64, (high.1 * MAZE_PITCH) as i64, false);
+            }
+        }
+    }
+
+    /// Whether a creature could stand on the position if it was not blocked, the border of a world with edges is walled apart from the terrain
+    fn is_inside(&self, x: i64, y: i64) -> bool {
+        self.wrap || ((1..self.width as i64 - 1).contains(&x) && (1..self.height as i64 - 1).contains(&y))
+    }
+
+    /// Position of `index`, the inverse of `TerrainWalls::index`
+    fn position(&self, index: usize) -> (i64, i64) {
+        ((index % self.width) as i64, (index / self.width) as i64)
+    }
+
+    /// Indices of the four positions a step away from `index`, around the edges of a wrapped world
+    fn neighbours(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        let (x, y) = self.position(index);
+        [(x, y - 1), (x + 1, y), (x, y + 1), (x - 1, y)].into_iter()
+            .filter(|&(x, y)| self.is_inside(x, y))
+            .filter_map(|(x, y)| self.index(x, y))
+    }
+
+    /// The open area every free position belongs to, positions that can reach each other share one
+    fn regions(&self) -> Vec<Option<usize>> {
+        let mut regions = vec![None; self.blocked.len()];
+        let mut count = 0;
+        for start in 0..self.blocked.len() {
+            let (x, y) = self.position(start);
+            if regions[start].is_some() || self.blocked[start] || !self.is_inside(x, y) {
+                continue;
+            }
+            regions[start] = Some(count);
+            let mut stack = vec![start];
+            while let Some(index) = stack.pop() {
+                for next in self.neighbours(index) {
+                    if regions[next].is_none() && !self.blocked[next] {
+                        regions[next] = Some(count);
+                        stack.push(next);
+                    }
+                }
+            }
+            count += 1;
+        }
+        regions
+    }
+
+    /// Opens the shortest way from `from` into the open area `region`.
+    fn carve_to(&mut self, from: usize, regions: &[Option<usize>], region: usize) {
+        let mut previous: Vec<Option<usize>> = vec![None; self.blocked.len()];
+        previous[from] = Some(from);
+        let mut queue = VecDeque::from([from]);
+        while let Some(index) = queue.pop_front() {
+            if regions[index] == Some(region) {
+                let mut step = index;
+                while step != from {
+                    self.blocked[step] = false;
+                    step = previous[step].expect("Every reached position has a way back");
+                }
+                return;
+            }
+            for next in self.neighbours(index).collect::<Vec<usize>>() {
+                if previous[next].is_none() {
+                    previous[next] = Some(index);
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+
+    /// Clears the surroundings of every spawner and a path from each into the largest open area, so no spawner is walled off.
+    pub fn connect(&mut self, spawners: &[(u16, u16)]) {
+        for spawner in spawners {
+            for y in -1..=1 {
+                for x in -1..=1 {
+                    self.set(spawner.0 as i64 + x, spawner.1 as i64 + y, false);
+                }
+            }
+        }
+        for spawner in spawners {
+            let Some(start) = self.index(spawner.0 as i64, spawner.1 as i64) else {
+                continue;
+            };
+            // A carved path only grows the largest area, but the area of the spawner may have been the largest before
+            let regions = self.regions();
+            let mut sizes: HashMap<usize, usize> = HashMap::new();
+            regions.iter().flatten().for_each(|region| *sizes.entry(*region).or_default() += 1);
+            let largest = sizes.into_iter().max_by_key(|&(region, size)| (size, std::cmp::Reverse(region))).map(|(region, _)| region);
+            if let Some(largest) = largest.filter(|largest| regions[start] != Some(*largest)) {
+                self.carve_to(start, &regions, largest);
+            }
+        }
+    }
+
+    pub fn positions(&self) -> Vec<(u16, u16)> {
+        (0..self.blocked.len()).filter(|index| self.blocked[*index])
+            .map(|index| ((index % self.width) as u16, (index / self.width) as u16))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::{CanvasParts, Element};
+    use crate::{Config, Simulation};
+
+    /// The area of every free position, positions reachable from each other in steps without diagonals share one
+    fn areas(canvas: &CanvasParts) -> Vec<Option<usize>> {
+        let (width, height) = (canvas.size.0 as i32, canvas.size.1 as i32);
+        let index = |(x, y): (i32, i32)| (y.rem_euclid(height) * width + x.rem_euclid(width)) as usize;
+        let free = |index: usize| !canvas.grid().at(((index as i32 % width) as u16, (index as i32 / width) as u16)).contains(&Element::Wall);
+        let mut areas = vec![None; (width * height) as usize];
+        for start in (0..areas.len()).filter(|start| free(*start)) {
+            if areas[start].is_some() {
+                continue;
+            }
+            areas[start] = Some(start);
+            let mut stack = vec![start];
+            while let Some(at) = stack.pop() {
+                let (x, y) = (at as i32 % width, at as i32 / width);
+                for next in [(x, y - 1), (x + 1, y), (x, y + 1), (x - 1, y)].map(index) {
+                    if areas[next].is_none() && free(next) {
+                        areas[next] = Some(start);
+                        stack.push(next);
+                    }
+                }
+            }
+        }
+        areas
+    }
+
+    #[test]
+    fn spawners_reach_the_largest_open_area() {
+        for terrain in [Terrain::Islands, Terrain::Caves, Terrain::Maze] {
+            for wrap in [false, true] {
+                for (seed, spawners) in (0..20).flat_map(|seed| [(seed, 1), (seed, 3)]) {
+                    let config = Config { seed, terrain: Some(terrain), wrap, spawners, ..Config::default() };
+                    config.validate().unwrap();
+                    let canvas = Simulation::new(config).canvas;
+                    let areas = areas(&canvas);
+                    let mut sizes = vec![0; areas.len()];
+                    areas.iter().flatten().for_each(|area| sizes[*area] += 1);
+                    let largest = *sizes.iter().max().unwrap();
+                    for spawner in canvas.interactable.iter().filter(|part| part.element == Element::Spawn) {
+                        let area = areas[(spawner.position.1 * canvas.size.0 + spawner.position.0) as usize].unwrap();
+                        assert_eq!(sizes[area], largest, "{:?} with seed {}, {} spawners and wrap {}", terrain, seed, spawners, wrap);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+use unicode_width::UnicodeWidthChar;
+
+use crate::components::Element;
+use crate::config::default_visuals;
+use crate::species::themed_elements;
+
+/// Glyph sets to draw the world with.
+#[derive(Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    /// Thin lines and symbols, not every font has all of them
+    Unicode,
+    /// Only characters every terminal can show
+    Ascii,
+    /// Shaded blocks
+    Blocks,
+}
+
+impl Theme {
+    pub fn visuals(self) -> HashMap<Element, char> {
+        match self {
+            Theme::Unicode => default_visuals(),
+            Theme::Ascii => HashMap::from([
+                (Element::Wall, '#'),
+                (Element::Spawn, 'S'),
+                (Element::BodyPartVert, '|'),
+                (Element::BodyPartHori, '-'),
+                (Element::BodyPartRightLean, '\\'),
+                (Element::BodyPartLeftLean, '/'),
+                (Element::Food, '*'),
+                (Element::BodyPartHead, '@'),
+                (Element::WespHead, '0'),
+                (Element::WespBody, 'o'),
+                (Element::LineVert, '|'),
+                (Element::LineHori, '-'),
+                (Element::LineRightLean, '\\'),
+                (Element::LineLeftLean, '/'),
+                (Element::LineUpRight, '+'),
+                (Element::LineUpLeft, '+'),
+                (Element::LineDownRight, '+'),
+                (Element::LineDownLeft, '+'),
+                (Element::LineCross, 'X'),
+            ]),
+            Theme::Blocks => {
+                // Bodies are solid whichever way they turn
+                let mut visuals: HashMap<Element, char> = themed_elements().map(|(_, element)| (element, '▓')).collect();
+                visuals.extend([
+                    (Element::Wall, '█'),
+                    (Element::Spawn, '▒'),
+                    (Element::Food, '▪'),
+                    (Element::BodyPartHead, '█'),
+                    (Element::WespHead, '■'),
+                    (Element::WespBody, '░'),
+                ]);
+                visuals
+            }
+        }
+    }
+}
+
+/// Layout of a theme file: glyphs by element name, the others are taken from `base`.
+#[derive(Deserialize)]
+struct ThemeFile {
+    base: Option<Theme>,
+    #[serde(flatten)]
+    glyphs: HashMap<String, char>,
+}
+
+/// Reads the glyphs of a theme file, a theme without `base` starts from the unicode one.
+pub fn load_theme_file(path: &Path) -> Result<HashMap<Element, char>, String> {
+    let content = fs::read_to_string(path).map_err(|err| format!("Cannot read {}: {}", path.display(), err))?;
+    let file: ThemeFile = toml::from_str(&content).map_err(|err| format!("Invalid theme file {}: {}", path.display(), err))?;
+    let mut visuals = file.base.unwrap_or(Theme::Unicode).visuals();
+    for (name, glyph) in file.glyphs {
+        let element = themed_elements().find(|(named, _)| *named == name).map(|(_, element)| element)
+            .ok_or_else(|| format!("Unknown element '{}' in {}", name, path.display()))?;
+        check_glyph_width(&name, glyph)?;
+        visuals.insert(element, glyph);
+    }
+    Ok(visuals)
+}
+
+/// Every glyph takes one cell of the grid, wider ones would push the rest of the row aside.
+pub fn check_glyph_width(name: &str, glyph: char) -> Result<(), String> {
+    match glyph.width() {
+        Some(1) => Ok(()),
+        Some(width) => Err(format!("The glyph '{}' of {} is {} columns wide, only glyphs of one column fit the grid", glyph, name, width)),
+        None => Err(format!("The glyph {:?} of {} is a control character", glyph, name)),
+    }
+}